@@ -0,0 +1,203 @@
+//! Custom derives for regl.
+//!
+//! `#[derive(Pod)]` marks a `#[repr(C)]` struct as plain old data that can be uploaded to
+//! buffers. It fails to compile if a field isn't `Pod` or if the struct has padding.
+//!
+//! `#[derive(Vertex)]` describes the layout of a `#[repr(C)]` vertex struct, so that the vertex
+//! attributes of a `VertexArray` don't have to be written out by hand. Every field becomes one
+//! attribute. The attribute is bound to the shader attribute with the same name as the field,
+//! unless the field is annotated otherwise:
+//!
+//! ```ignore
+//! #[derive(Vertex)]
+//! #[repr(C)]
+//! struct MyVertex {
+//!     #[vertex(location = 0)]
+//!     position: [f32; 3],
+//!     #[vertex(name = "in_color", normalized)]
+//!     color: [u8; 4],
+//!     #[vertex(integer)]
+//!     material: u32,
+//! }
+//! ```
+//!
+//! `integer` fields are read with glVertexAttribIPointer, so their components must be integers;
+//! a floating point field marked `integer` fails to compile.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Error, Fields, Field, LitInt, LitStr};
+use syn::spanned::Spanned;
+
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match vertex_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn vertex_impl(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    try!(check_repr_c(input, "Vertex"));
+    let fields = match input.data {
+        Data::Struct(ref data) => {
+            match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => {
+                    return Err(Error::new_spanned(input,
+                                                  "#[derive(Vertex)] requires named fields"))
+                }
+            }
+        }
+        _ => return Err(Error::new_spanned(input, "#[derive(Vertex)] only supports structs")),
+    };
+
+    let mut vertex_fields = vec![];
+    for field in fields {
+        vertex_fields.push(try!(vertex_field(field)));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::regl::Vertex for #name #ty_generics #where_clause {
+            fn fields() -> ::std::vec::Vec<::regl::VertexField> {
+                #[allow(dead_code)]
+                fn assert_integer<T: ::regl::IntegerVertexComponent>() {}
+                vec![#(#vertex_fields),*]
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(Pod)]
+pub fn derive_pod(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match pod_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn pod_impl(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    try!(check_repr_c(input, "Pod"));
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics,
+                                      "#[derive(Pod)] doesn't support generic structs"));
+    }
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => return Err(Error::new_spanned(input, "#[derive(Pod)] only supports structs")),
+    };
+    let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+
+    let name = &input.ident;
+    Ok(quote! {
+        unsafe impl ::regl::Pod for #name {}
+
+        const _: () = {
+            fn assert_pod<T: ::regl::Pod>() {}
+            #[allow(dead_code)]
+            fn assert_fields_pod() {
+                #(assert_pod::<#field_types>();)*
+            }
+            assert!(::std::mem::size_of::<#name>() ==
+                    0 #(+ ::std::mem::size_of::<#field_types>())*,
+                    "#[derive(Pod)] struct must not contain padding");
+        };
+    })
+}
+
+fn check_repr_c(input: &DeriveInput, derive: &str) -> Result<(), Error> {
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        try!(attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            }
+            Ok(())
+        }));
+    }
+    if repr_c {
+        Ok(())
+    } else {
+        Err(Error::new_spanned(&input.ident,
+                               format!("#[derive({})] requires #[repr(C)] to have a stable layout",
+                                       derive)))
+    }
+}
+
+fn vertex_field(field: &Field) -> Result<TokenStream2, Error> {
+    let ident = field.ident.as_ref().unwrap();
+    let ty = &field.ty;
+    let mut location = None;
+    let mut name = None;
+    let mut normalized = false;
+    let mut integer = false;
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        try!(attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("location") {
+                let value: LitInt = try!(try!(meta.value()).parse());
+                location = Some(try!(value.base10_parse::<u32>()));
+            } else if meta.path.is_ident("name") {
+                let value: LitStr = try!(try!(meta.value()).parse());
+                name = Some(value.value());
+            } else if meta.path.is_ident("normalized") {
+                normalized = true;
+            } else if meta.path.is_ident("integer") {
+                integer = true;
+            } else {
+                return Err(meta.error("expected `location`, `name`, `normalized` or `integer`"));
+            }
+            Ok(())
+        }));
+    }
+
+    if normalized && integer {
+        return Err(Error::new_spanned(ident,
+                                      "a vertex field can't be both `normalized` and `integer`"));
+    }
+    let location = match (location, name) {
+        (Some(_), Some(_)) => {
+            return Err(Error::new_spanned(ident,
+                                          "a vertex field can't have both `location` and `name`"))
+        }
+        (Some(index), None) => quote!(::regl::VertexLocation::Index(#index)),
+        (None, Some(name)) => quote!(::regl::VertexLocation::Name(#name)),
+        (None, None) => {
+            let name = ident.to_string();
+            quote!(::regl::VertexLocation::Name(#name))
+        }
+    };
+
+    let vertex_field = quote! {
+        ::regl::VertexField {
+            location: #location,
+            size: <#ty as ::regl::VertexComponent>::size(),
+            attribute_type: <#ty as ::regl::VertexComponent>::attribute_type(),
+            normalized: #normalized,
+            integer: #integer,
+            offset: ::std::mem::offset_of!(Self, #ident) as u32,
+        }
+    };
+    if integer {
+        // Floating point fields can't be read with glVertexAttribIPointer; fail to compile
+        // instead of with GL_INVALID_ENUM at runtime
+        Ok(quote_spanned! {ty.span()=>
+            {
+                assert_integer::<#ty>();
+                #vertex_field
+            }
+        })
+    } else {
+        Ok(vertex_field)
+    }
+}
@@ -0,0 +1,381 @@
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::cmp::min;
+use std::mem::size_of;
+use ReglResult;
+use ReglError;
+use pod::Pod;
+use buffer::{Buffer, BufferCreationSupport, BufferTarget, IndexedBufferTarget, BufferUsage};
+
+#[derive(Debug,Clone,Copy)]
+struct Allocation {
+    offset: usize,
+    len: usize,
+    alignment: usize,
+}
+
+#[derive(Debug)]
+struct AllocatorState {
+    next_id: u64,
+    allocations: HashMap<u64, Allocation>,
+    /// Free ranges as (offset, len), sorted by offset, never adjacent to each other.
+    free: Vec<(usize, usize)>,
+}
+
+#[derive(Debug)]
+struct AllocatorInner {
+    buffer: Buffer,
+    state: RefCell<AllocatorState>,
+}
+
+/// Packs many small allocations, like the vertices and indices of many meshes, into a single
+/// buffer.
+///
+/// Allocations are handed out as `BufferSlice`s, which free their range when dropped. The
+/// free ranges are kept in a first fit free list.
+#[derive(Debug)]
+pub struct BufferAllocator {
+    inner: Rc<AllocatorInner>,
+}
+
+/// A range of a buffer handed out by a `BufferAllocator`. The range is freed when the slice
+/// is dropped.
+///
+/// The offset of a slice changes if the allocator is defragmented, so it should be queried
+/// again after `BufferAllocator::defragment` instead of being stored.
+#[derive(Debug)]
+pub struct BufferSlice {
+    inner: Rc<AllocatorInner>,
+    id: u64,
+}
+
+impl BufferAllocator {
+    pub fn new<C: BufferCreationSupport>(support: &mut C,
+                                         target: BufferTarget,
+                                         usage: BufferUsage,
+                                         data_len: usize)
+                                         -> ReglResult<BufferAllocator> {
+        let buffer = try!(Buffer::with_size(support, target, usage, data_len));
+        let state = AllocatorState {
+            next_id: 0,
+            allocations: HashMap::new(),
+            free: if data_len > 0 { vec![(0, data_len)] } else { vec![] },
+        };
+        Ok(BufferAllocator {
+            inner: Rc::new(AllocatorInner {
+                buffer: buffer,
+                state: RefCell::new(state),
+            }),
+        })
+    }
+
+    /// Allocates `len` bytes at an offset that is a multiple of `alignment`.
+    pub fn allocate(&self, len: usize, alignment: usize) -> ReglResult<BufferSlice> {
+        let alignment = if alignment > 0 { alignment } else { 1 };
+        let mut state = self.inner.state.borrow_mut();
+        let offset = match state.take_free(len, alignment) {
+            Some(offset) => offset,
+            None => return Err(ReglError::OutOfBufferSpace),
+        };
+        let id = state.next_id;
+        state.next_id += 1;
+        state.allocations.insert(id,
+                                 Allocation {
+                                     offset: offset,
+                                     len: len,
+                                     alignment: alignment,
+                                 });
+        Ok(BufferSlice {
+            inner: self.inner.clone(),
+            id: id,
+        })
+    }
+
+    /// Allocates room for `len` elements of type `T`, aligned so that the offset is a whole
+    /// number of elements. Such a slice can be used with `BufferSlice::first_element`, e.g.
+    /// as the base vertex of an indexed draw.
+    pub fn allocate_elements<T: Pod>(&self, len: usize) -> ReglResult<BufferSlice> {
+        self.allocate(len * size_of::<T>(), size_of::<T>())
+    }
+
+    /// Allocates a range that can be bound to the binding points of `target`.
+    pub fn allocate_for_binding(&self,
+                                target: IndexedBufferTarget,
+                                len: usize)
+                                -> ReglResult<BufferSlice> {
+        let alignment = self.inner.buffer.offset_alignment(target);
+        self.allocate(len, alignment)
+    }
+
+    /// Number of free bytes, possibly fragmented into several ranges.
+    pub fn free_len(&self) -> usize {
+        self.inner.state.borrow().free.iter().map(|&(_, len)| len).sum()
+    }
+
+    /// Length of the largest range that can be allocated without defragmenting.
+    pub fn largest_free_len(&self) -> usize {
+        self.inner.state.borrow().free.iter().map(|&(_, len)| len).max().unwrap_or(0)
+    }
+
+    /// Moves all allocations to the beginning of the buffer with GPU side copies, so that the
+    /// free space forms a single range at the end. Returns true if anything was moved, in
+    /// which case the offsets of the slices must be queried again, and vertex arrays set up
+    /// with the old offsets must be updated.
+    pub fn defragment(&self) -> ReglResult<bool> {
+        let mut state = self.inner.state.borrow_mut();
+        let mut allocations: Vec<(u64, Allocation)> = state.allocations
+                                                          .iter()
+                                                          .map(|(&id, &a)| (id, a))
+                                                          .collect();
+        allocations.sort_by_key(|&(_, a)| a.offset);
+
+        let mut moved = false;
+        let mut free = vec![];
+        let mut cursor = 0;
+        for &(id, allocation) in &allocations {
+            // Aligned cursor never passes the allocation, as its offset is aligned too
+            let offset = align(cursor, allocation.alignment);
+            if offset < allocation.offset {
+                try!(move_down(&self.inner.buffer, allocation.offset, offset, allocation.len));
+                state.allocations.get_mut(&id).unwrap().offset = offset;
+                moved = true;
+            }
+            if cursor < offset {
+                free.push((cursor, offset - cursor));
+            }
+            cursor = offset + allocation.len;
+        }
+        let data_len = self.inner.buffer.data_len();
+        if cursor < data_len {
+            free.push((cursor, data_len - cursor));
+        }
+        state.free = free;
+        Ok(moved)
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.inner.buffer
+    }
+}
+
+impl BufferSlice {
+    /// Offset of the slice from the beginning of the buffer, in bytes.
+    pub fn offset(&self) -> usize {
+        self.allocation().offset
+    }
+
+    /// Length of the slice in bytes.
+    pub fn len(&self) -> usize {
+        self.allocation().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Offset of the slice in elements of type `T`, e.g. the `base_vertex` of an indexed draw.
+    /// The slice should have been allocated with `BufferAllocator::allocate_elements::<T>`.
+    pub fn first_element<T: Pod>(&self) -> u32 {
+        (self.offset() / size_of::<T>()) as u32
+    }
+
+    /// Overwrites data within the slice. `byte_offset` is relative to the beginning of the
+    /// slice.
+    pub fn update_data<T: Pod>(&self, byte_offset: usize, data: &[T]) -> ReglResult<()> {
+        let allocation = self.allocation();
+        if byte_offset + data.len() * size_of::<T>() > allocation.len {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        self.inner.buffer.update_data(allocation.offset + byte_offset, data)
+    }
+
+    /// Binds the whole slice to the binding point `index` of `target`.
+    pub fn bind_range(&self, target: IndexedBufferTarget, index: u32) -> ReglResult<()> {
+        let allocation = self.allocation();
+        self.inner.buffer.bind_range(target, index, allocation.offset, allocation.len)
+    }
+
+    /// The buffer the slice is in.
+    pub fn buffer(&self) -> &Buffer {
+        &self.inner.buffer
+    }
+
+    fn allocation(&self) -> Allocation {
+        self.inner.state.borrow().allocations[&self.id]
+    }
+}
+
+impl Drop for BufferSlice {
+    fn drop(&mut self) {
+        let mut state = self.inner.state.borrow_mut();
+        if let Some(allocation) = state.allocations.remove(&self.id) {
+            state.give_free(allocation.offset, allocation.len);
+        }
+    }
+}
+
+impl AllocatorState {
+    fn take_free(&mut self, len: usize, alignment: usize) -> Option<usize> {
+        let fits = |&(offset, free_len): &(usize, usize)| {
+            align(offset, alignment) + len <= offset + free_len
+        };
+        let i = match self.free.iter().position(fits) {
+            Some(i) => i,
+            None => return None,
+        };
+        let aligned = align(self.free[i].0, alignment);
+        let (offset, free_len) = self.free.remove(i);
+        let end = offset + free_len;
+        if aligned + len < end {
+            self.free.insert(i, (aligned + len, end - aligned - len));
+        }
+        if offset < aligned {
+            self.free.insert(i, (offset, aligned - offset));
+        }
+        Some(aligned)
+    }
+
+    fn give_free(&mut self, offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let i = self.free.iter().position(|&(o, _)| o > offset).unwrap_or(self.free.len());
+        self.free.insert(i, (offset, len));
+        // Merge with the following range, then with the preceding one
+        if i + 1 < self.free.len() && offset + len == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+    }
+}
+
+/// Copies data to a lower offset within the buffer. Copies between overlapping ranges aren't
+/// allowed, so the data is copied in chunks no longer than the distance moved.
+fn move_down(buffer: &Buffer, from: usize, to: usize, len: usize) -> ReglResult<()> {
+    let distance = from - to;
+    let mut done = 0;
+    while done < len {
+        let chunk = min(distance, len - done);
+        try!(buffer.copy_to(buffer, from + done, to + done, chunk));
+        done += chunk;
+    }
+    Ok(())
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::AllocatorState;
+
+    fn state(data_len: usize) -> AllocatorState {
+        AllocatorState {
+            next_id: 0,
+            allocations: HashMap::new(),
+            free: vec![(0, data_len)],
+        }
+    }
+
+    #[test]
+    fn allocates_first_fit() {
+        let mut state = state(100);
+        assert_eq!(state.take_free(10, 1), Some(0));
+        assert_eq!(state.take_free(20, 1), Some(10));
+        assert_eq!(state.free, vec![(30, 70)]);
+    }
+
+    #[test]
+    fn allocating_everything_leaves_no_free_range() {
+        let mut state = state(100);
+        assert_eq!(state.take_free(100, 1), Some(0));
+        assert_eq!(state.free, vec![]);
+    }
+
+    #[test]
+    fn reuses_freed_range() {
+        let mut state = state(100);
+        state.take_free(10, 1);
+        state.take_free(10, 1);
+        state.give_free(0, 10);
+        assert_eq!(state.take_free(5, 1), Some(0));
+        assert_eq!(state.free, vec![(5, 5), (20, 80)]);
+    }
+
+    #[test]
+    fn merges_with_following_range() {
+        let mut state = state(100);
+        state.take_free(10, 1);
+        state.take_free(10, 1);
+        state.give_free(10, 10);
+        assert_eq!(state.free, vec![(10, 90)]);
+    }
+
+    #[test]
+    fn merges_with_preceding_range() {
+        let mut state = state(30);
+        state.take_free(10, 1);
+        state.take_free(10, 1);
+        state.take_free(10, 1);
+        state.give_free(0, 10);
+        state.give_free(10, 10);
+        assert_eq!(state.free, vec![(0, 20)]);
+    }
+
+    #[test]
+    fn merges_with_both_neighbours() {
+        let mut state = state(100);
+        state.take_free(10, 1);
+        state.take_free(10, 1);
+        state.take_free(10, 1);
+        state.give_free(0, 10);
+        state.give_free(20, 10);
+        assert_eq!(state.free, vec![(0, 10), (20, 80)]);
+        state.give_free(10, 10);
+        assert_eq!(state.free, vec![(0, 100)]);
+    }
+
+    #[test]
+    fn keeps_alignment_padding_free() {
+        let mut state = state(100);
+        state.take_free(3, 1);
+        assert_eq!(state.take_free(8, 16), Some(16));
+        assert_eq!(state.free, vec![(3, 13), (24, 76)]);
+        // The padding is used by allocations that fit in it
+        assert_eq!(state.take_free(4, 4), Some(4));
+        assert_eq!(state.free, vec![(3, 1), (8, 8), (24, 76)]);
+    }
+
+    #[test]
+    fn skips_ranges_too_small_after_alignment() {
+        let mut state = state(64);
+        state.take_free(1, 1);
+        state.take_free(15, 1);
+        state.give_free(0, 1);
+        // (0, 1) is aligned but too short, so the allocation goes after the used range
+        assert_eq!(state.take_free(8, 8), Some(16));
+    }
+
+    #[test]
+    fn runs_out_of_space() {
+        let mut state = state(32);
+        assert_eq!(state.take_free(33, 1), None);
+        state.take_free(8, 1);
+        state.take_free(8, 1);
+        state.take_free(8, 1);
+        state.give_free(8, 8);
+        // 16 bytes are free, but not in one range
+        assert_eq!(state.take_free(16, 1), None);
+        assert_eq!(state.take_free(8, 1), Some(8));
+        assert_eq!(state.take_free(8, 1), Some(24));
+        assert_eq!(state.take_free(1, 1), None);
+    }
+}
@@ -1,12 +1,17 @@
 
 use std::rc::Rc;
+use std::cell::Cell;
+use std::cmp::min;
+use std::ptr;
 use std::fmt::Debug;
 use std::mem::size_of;
-use gl::types::{GLenum, GLsizeiptr, GLintptr, GLvoid};
+use gl::types::{GLenum, GLbitfield, GLsizeiptr, GLintptr, GLvoid};
 use id::{Id, GenerateId, GlId};
 use ReglResult;
 use ReglError;
-use tracker::BindIf;
+use pod::Pod;
+use mapping::{self, MapAccess, ReadMapping, WriteMapping, ReadWriteMapping};
+use tracker::{BindIf, BindNone, BindIndexedIf, IndexedBinding};
 use resource::ResourceCreationSupport;
 use vertex_array::{VertexArray, bind_vertex_array};
 
@@ -15,7 +20,15 @@ pub trait BufferCreationSupport : ResourceCreationSupport {
 }
 
 pub trait BufferSupport : BindIf<VertexBufferTag> + BindIf<IndexBufferTag>
-    + BindIf<UniformBufferTag> + Debug {}
+    + BindIf<UniformBufferTag> + BindIf<CopyReadBufferTag> + BindIf<CopyWriteBufferTag>
+    + BindNone<CopyReadBufferTag> + BindNone<CopyWriteBufferTag>
+    + BindIf<ShaderStorageBufferTag> + BindIf<AtomicCounterBufferTag>
+    + BindIf<DispatchIndirectBufferTag>
+    + BindIndexedIf<UniformBufferTag> + BindIndexedIf<ShaderStorageBufferTag>
+    + BindIndexedIf<AtomicCounterBufferTag> + Debug {
+    /// Required alignment of the offset of a range bound to an indexed binding point.
+    fn offset_alignment(&self, target: IndexedBufferTarget) -> usize;
+}
 
 pub trait UpdateBuffer {
 
@@ -27,12 +40,40 @@ pub struct VertexBufferTag;
 pub struct IndexBufferTag;
 #[allow(dead_code)]
 pub struct UniformBufferTag;
+#[allow(dead_code)]
+pub struct ShaderStorageBufferTag;
+#[allow(dead_code)]
+pub struct AtomicCounterBufferTag;
+#[allow(dead_code)]
+pub struct CopyReadBufferTag;
+#[allow(dead_code)]
+pub struct CopyWriteBufferTag;
+#[allow(dead_code)]
+pub struct DispatchIndirectBufferTag;
 
 #[derive(Debug,Clone,Copy)]
 pub enum BufferTarget {
     VertexBuffer,
     IndexBuffer,
     UniformBuffer,
+    ShaderStorageBuffer,
+    AtomicCounterBuffer,
+    CopyReadBuffer,
+    CopyWriteBuffer,
+    /// Work group counts for `Context::dispatch_compute_indirect`.
+    DispatchIndirectBuffer,
+}
+
+/// Targets with indexed binding points, that buffer ranges can be bound to.
+#[derive(Debug,Clone,Copy)]
+pub enum IndexedBufferTarget {
+    /// Binding points for uniform blocks; see `Program::uniform_block_binding`.
+    UniformBuffer,
+    /// Binding points for shader storage blocks; see `Program::storage_block_binding`.
+    ShaderStorageBuffer,
+    /// Binding points for atomic counters. The binding is fixed in the shader; see
+    /// `UniformInfo::atomic_counter_buffers`.
+    AtomicCounterBuffer,
 }
 
 #[derive(Debug,Clone,Copy)]
@@ -54,9 +95,10 @@ pub struct BaseBuffer {
     uid: Id,
     gl_id: GlId,
     target: BufferTarget,
-    usage: BufferUsage,
+    /// None if the buffer has immutable storage.
+    usage: Option<BufferUsage>,
     default_vertex_array: Rc<VertexArray>,
-    data_len: usize,
+    data_len: Cell<usize>,
 }
 
 impl BaseBuffer {
@@ -78,19 +120,49 @@ impl BaseBuffer {
                 BindIf::<UniformBufferTag>::bind_if(&*self.shared_context,
                                                     &self.uid,
                                                     &|| self.gl_bind(target)),
+            BufferTarget::ShaderStorageBuffer =>
+                BindIf::<ShaderStorageBufferTag>::bind_if(&*self.shared_context,
+                                                          &self.uid,
+                                                          &|| self.gl_bind(target)),
+            BufferTarget::AtomicCounterBuffer =>
+                BindIf::<AtomicCounterBufferTag>::bind_if(&*self.shared_context,
+                                                          &self.uid,
+                                                          &|| self.gl_bind(target)),
+            BufferTarget::CopyReadBuffer =>
+                BindIf::<CopyReadBufferTag>::bind_if(&*self.shared_context,
+                                                     &self.uid,
+                                                     &|| self.gl_bind(target)),
+            BufferTarget::CopyWriteBuffer =>
+                BindIf::<CopyWriteBufferTag>::bind_if(&*self.shared_context,
+                                                      &self.uid,
+                                                      &|| self.gl_bind(target)),
+            BufferTarget::DispatchIndirectBuffer =>
+                BindIf::<DispatchIndirectBufferTag>::bind_if(&*self.shared_context,
+                                                             &self.uid,
+                                                             &|| self.gl_bind(target)),
         }
     }
 
+    /// Size of the buffer in bytes.
+    pub fn data_len(&self) -> usize {
+        self.data_len.get()
+    }
+
     /// Forces the bind to happen; used to bind IBO to VAO
     pub fn bind_as_indices_anyway(&self) {
         BindIf::<IndexBufferTag>::bind_if(&*self.shared_context, &self.uid, &|| ());
         self.gl_bind(BufferTarget::IndexBuffer);
     }
 
-    pub fn update_data<T>(&self, byte_offset: usize, data: &[T]) -> ReglResult<()> {
+    pub fn update_data<T: Pod>(&self, byte_offset: usize, data: &[T]) -> ReglResult<()> {
+        // Immutable storage is created without GL_DYNAMIC_STORAGE_BIT, so the GL would reject
+        // the update
+        if self.usage.is_none() {
+            return Err(ReglError::ImmutableBufferStorage);
+        }
         let data_len = len_in_bytes(data);
         let data_end = data_len as usize + byte_offset;
-        if data_end > self.data_len as usize {
+        if data_end > self.data_len.get() {
             return Err(ReglError::BufferDataOutOfRange);
         }
         self.bind_default();
@@ -101,6 +173,131 @@ impl BaseBuffer {
         Ok(())
     }
 
+    /// Binds the range to the binding point `index` of `target`. The generic binding of the
+    /// target is changed as a side effect.
+    pub fn bind_range(&self,
+                      target: IndexedBufferTarget,
+                      index: u32,
+                      offset: usize,
+                      size: usize)
+                      -> ReglResult<()> {
+        if offset + size > self.data_len.get() {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        if offset % self.offset_alignment(target) != 0 {
+            return Err(ReglError::MisalignedBufferOffset);
+        }
+        self.bind_indexed(target, index, offset, size, &|| {
+            glcall!(BindBufferRange(gl_indexed_target(target),
+                                    index,
+                                    self.gl_id,
+                                    offset as GLintptr,
+                                    size as GLsizeiptr));
+        });
+        Ok(())
+    }
+
+    /// Binds the whole buffer to the binding point `index` of `target`. The generic binding of
+    /// the target is changed as a side effect.
+    pub fn bind_base(&self, target: IndexedBufferTarget, index: u32) {
+        // The size is recorded so that a range binding covering the whole buffer is not
+        // mistaken for the same binding if the buffer is resized.
+        let size = self.data_len.get();
+        self.bind_indexed(target, index, 0, size, &|| {
+            glcall!(BindBufferBase(gl_indexed_target(target), index, self.gl_id));
+        });
+    }
+
+    pub fn offset_alignment(&self, target: IndexedBufferTarget) -> usize {
+        self.shared_context.offset_alignment(target)
+    }
+
+    fn bind_indexed(&self,
+                    target: IndexedBufferTarget,
+                    index: u32,
+                    offset: usize,
+                    size: usize,
+                    bind: &Fn()) {
+        let binding = IndexedBinding {
+            id: self.uid.weak(),
+            offset: offset,
+            size: size,
+        };
+        let shared_context = &*self.shared_context;
+        // Binding to an indexed binding point also binds to the generic binding point
+        match target {
+            IndexedBufferTarget::UniformBuffer => {
+                let bind_both = || {
+                    bind();
+                    BindIf::<UniformBufferTag>::bind_if(shared_context, &self.uid, &|| ());
+                };
+                BindIndexedIf::<UniformBufferTag>::bind_indexed_if(shared_context,
+                                                                   index,
+                                                                   &binding,
+                                                                   &bind_both)
+            }
+            IndexedBufferTarget::ShaderStorageBuffer => {
+                let bind_both = || {
+                    bind();
+                    BindIf::<ShaderStorageBufferTag>::bind_if(shared_context, &self.uid, &|| ());
+                };
+                BindIndexedIf::<ShaderStorageBufferTag>::bind_indexed_if(shared_context,
+                                                                         index,
+                                                                         &binding,
+                                                                         &bind_both)
+            }
+            IndexedBufferTarget::AtomicCounterBuffer => {
+                let bind_both = || {
+                    bind();
+                    BindIf::<AtomicCounterBufferTag>::bind_if(shared_context, &self.uid, &|| ());
+                };
+                BindIndexedIf::<AtomicCounterBufferTag>::bind_indexed_if(shared_context,
+                                                                         index,
+                                                                         &binding,
+                                                                         &bind_both)
+            }
+        }
+    }
+
+    /// Reads data through the copy read binding, leaving the other bindings alone.
+    pub fn read_data<T: Pod>(&self, byte_offset: usize, data: &mut [T]) -> ReglResult<()> {
+        let data_len = len_in_bytes(data);
+        if data_len as usize + byte_offset > self.data_len.get() {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        self.bind_target(BufferTarget::CopyReadBuffer);
+        glcall!(GetBufferSubData(::gl::COPY_READ_BUFFER,
+                                 byte_offset as GLintptr,
+                                 data_len,
+                                 data.as_mut_ptr() as *mut GLvoid));
+        Ok(())
+    }
+
+    /// Copies through the copy read and copy write bindings, leaving the other bindings alone.
+    pub fn copy_to(&self,
+                   destination: &BaseBuffer,
+                   source_offset: usize,
+                   destination_offset: usize,
+                   len: usize)
+                   -> ReglResult<()> {
+        if source_offset + len > self.data_len.get() ||
+           destination_offset + len > destination.data_len.get() {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        if self.uid == destination.uid && source_offset < destination_offset + len &&
+           destination_offset < source_offset + len {
+            return Err(ReglError::OverlappingBufferCopy);
+        }
+        self.bind_target(BufferTarget::CopyReadBuffer);
+        destination.bind_target(BufferTarget::CopyWriteBuffer);
+        glcall!(CopyBufferSubData(::gl::COPY_READ_BUFFER,
+                                  ::gl::COPY_WRITE_BUFFER,
+                                  source_offset as GLintptr,
+                                  destination_offset as GLintptr,
+                                  len as GLsizeiptr));
+        Ok(())
+    }
+
     fn bind_with_default_vao(&self) {
         bind_vertex_array(&*self.default_vertex_array);
         self.bind_target(BufferTarget::IndexBuffer);
@@ -114,14 +311,109 @@ impl BaseBuffer {
         }
     }
 
-    fn initial_data<T>(&self, data: &[T]) {
-        let data_len = len_in_bytes(data);
-        assert_eq!(self.data_len, data_len as usize);
+    /// Specifies new storage for the buffer, initialized from `data` unless it is null.
+    fn buffer_data(&self, data_len: usize, data: *const GLvoid) -> ReglResult<()> {
+        let usage = match self.usage {
+            Some(usage) => usage,
+            None => return Err(ReglError::ImmutableBufferStorage),
+        };
         self.bind_default();
         glcall!(BufferData(gl_target(self.target),
-                           data_len,
-                           data.as_ptr() as *const GLvoid,
-                           gl_usage(self.usage)))
+                           data_len as GLsizeiptr,
+                           data,
+                           gl_usage(usage)));
+        self.data_len.set(data_len);
+        Ok(())
+    }
+
+    fn initial_data<T: Pod>(&self, data: &[T]) -> ReglResult<()> {
+        self.buffer_data(len_in_bytes(data) as usize, data.as_ptr() as *const GLvoid)
+    }
+
+    /// Allocates immutable storage of `data_len` bytes with undefined contents.
+    fn storage(&self, data_len: usize, flags: GLbitfield) {
+        assert!(self.usage.is_none());
+        self.bind_default();
+        glcall!(BufferStorage(gl_target(self.target),
+                              data_len as GLsizeiptr,
+                              ptr::null::<GLvoid>(),
+                              flags));
+        self.data_len.set(data_len);
+    }
+
+    pub fn resize(&self, data_len: usize, preserve_contents: bool) -> ReglResult<()> {
+        if !preserve_contents {
+            return self.buffer_data(data_len, ptr::null());
+        }
+        if self.usage.is_none() {
+            return Err(ReglError::ImmutableBufferStorage);
+        }
+        let preserved_len = min(self.data_len.get(), data_len);
+        if preserved_len == 0 {
+            return self.buffer_data(data_len, ptr::null());
+        }
+        // The contents are copied to a temporary buffer and back on the GPU. The temporary
+        // buffer has no uid, so the trackers are told that they don't know what's bound.
+        let mut temporary = 0;
+        glcall!(GenBuffers(1, &mut temporary));
+        self.bind_target(BufferTarget::CopyReadBuffer);
+        glcall!(BindBuffer(::gl::COPY_WRITE_BUFFER, temporary));
+        BindNone::<CopyWriteBufferTag>::bind_none(&*self.shared_context);
+        glcall!(BufferData(::gl::COPY_WRITE_BUFFER,
+                           preserved_len as GLsizeiptr,
+                           ptr::null::<GLvoid>(),
+                           ::gl::STREAM_COPY));
+        glcall!(CopyBufferSubData(::gl::COPY_READ_BUFFER,
+                                  ::gl::COPY_WRITE_BUFFER,
+                                  0,
+                                  0,
+                                  preserved_len as GLsizeiptr));
+
+        let result = self.buffer_data(data_len, ptr::null());
+        if result.is_ok() {
+            glcall!(BindBuffer(::gl::COPY_READ_BUFFER, temporary));
+            BindNone::<CopyReadBufferTag>::bind_none(&*self.shared_context);
+            self.bind_target(BufferTarget::CopyWriteBuffer);
+            glcall!(CopyBufferSubData(::gl::COPY_READ_BUFFER,
+                                      ::gl::COPY_WRITE_BUFFER,
+                                      0,
+                                      0,
+                                      preserved_len as GLsizeiptr));
+        }
+        glcall!(DeleteBuffers(1, &temporary));
+        result
+    }
+
+    pub fn orphan(&self) -> ReglResult<()> {
+        let data_len = self.data_len.get();
+        self.buffer_data(data_len, ptr::null())
+    }
+
+    /// Maps the byte range and returns the pointer to it, which is null if mapping failed.
+    pub fn map_range(&self,
+                     byte_offset: usize,
+                     byte_len: usize,
+                     access: GLbitfield)
+                     -> *mut GLvoid {
+        self.bind_default();
+        glcall!(MapBufferRange(gl_target(self.target),
+                               byte_offset as GLintptr,
+                               byte_len as GLsizeiptr,
+                               access))
+    }
+
+    /// Offset is relative to the beginning of the mapped range.
+    pub fn flush_mapped_range(&self, byte_offset: usize, byte_len: usize) {
+        self.bind_default();
+        glcall!(FlushMappedBufferRange(gl_target(self.target),
+                                       byte_offset as GLintptr,
+                                       byte_len as GLsizeiptr));
+    }
+
+    /// Returns false if the contents of the buffer were corrupted while it was mapped.
+    pub fn unmap(&self) -> bool {
+        self.bind_default();
+        glcall!(UnmapBuffer(gl_target(self.target))) == ::gl::TRUE
     }
 
     fn gl_bind(&self, target: BufferTarget) {
@@ -141,37 +433,173 @@ pub struct Buffer {
 }
 
 impl Buffer {
-    pub fn new<C: BufferCreationSupport, T>(support: &mut C,
-                                            target: BufferTarget,
-                                            usage: BufferUsage,
-                                            data: &[T])
-                                            -> ReglResult<Buffer> {
-        let mut gl_id = 0;
-        glcall!(GenBuffers(1, &mut gl_id));
-        let base_buffer = BaseBuffer {
-            shared_context: support.get_shared_context(),
-            uid: support.generate_id(),
-            gl_id: gl_id,
-            target: target,
-            usage: usage,
-            default_vertex_array: support.get_default_vertex_array(),
-            data_len: len_in_bytes(data) as usize,
-        };
+    pub fn new<C: BufferCreationSupport, T: Pod>(support: &mut C,
+                                                 target: BufferTarget,
+                                                 usage: BufferUsage,
+                                                 data: &[T])
+                                                 -> ReglResult<Buffer> {
+        let base_buffer = new_base_buffer(support, target, Some(usage));
 
-        base_buffer.initial_data(data);
+        try!(base_buffer.initial_data(data));
 
         Ok(Buffer { base_buffer: Rc::new(base_buffer) })
     }
 
-    pub fn update_data<T>(&self, byte_offset: usize, data: &[T]) -> ReglResult<()> {
+    /// Creates a buffer of `data_len` bytes without initializing its contents.
+    pub fn with_size<C: BufferCreationSupport>(support: &mut C,
+                                               target: BufferTarget,
+                                               usage: BufferUsage,
+                                               data_len: usize)
+                                               -> ReglResult<Buffer> {
+        let base_buffer = new_base_buffer(support, target, Some(usage));
+
+        try!(base_buffer.buffer_data(data_len, ptr::null()));
+
+        Ok(Buffer { base_buffer: Rc::new(base_buffer) })
+    }
+
+    /// Fails for buffers with immutable storage, such as the buffer of a `StreamBuffer`; write
+    /// to those through their mapping instead.
+    pub fn update_data<T: Pod>(&self, byte_offset: usize, data: &[T]) -> ReglResult<()> {
         self.base_buffer.update_data(byte_offset, data)
     }
+
+    /// Size of the buffer in bytes.
+    pub fn data_len(&self) -> usize {
+        self.base_buffer.data_len()
+    }
+
+    /// Binds `size` bytes starting from `offset` to the binding point `index` of `target`.
+    /// The offset must be a multiple of `offset_alignment(target)`.
+    pub fn bind_range(&self,
+                      target: IndexedBufferTarget,
+                      index: u32,
+                      offset: usize,
+                      size: usize)
+                      -> ReglResult<()> {
+        self.base_buffer.bind_range(target, index, offset, size)
+    }
+
+    /// Binds the whole buffer to the binding point `index` of `target`.
+    pub fn bind_base(&self, target: IndexedBufferTarget, index: u32) {
+        self.base_buffer.bind_base(target, index)
+    }
+
+    /// Required alignment of offsets given to `bind_range` for `target`.
+    pub fn offset_alignment(&self, target: IndexedBufferTarget) -> usize {
+        self.base_buffer.offset_alignment(target)
+    }
+
+    /// Reads `data.len()` elements from the buffer, starting at `byte_offset`.
+    pub fn read_data<T: Pod>(&self, byte_offset: usize, data: &mut [T]) -> ReglResult<()> {
+        self.base_buffer.read_data(byte_offset, data)
+    }
+
+    /// Sets `count` atomic counters starting at `byte_offset` to `value`.
+    pub fn reset_atomic_counters(&self,
+                                 byte_offset: usize,
+                                 count: usize,
+                                 value: u32)
+                                 -> ReglResult<()> {
+        self.base_buffer.update_data(byte_offset, &vec![value; count])
+    }
+
+    /// Reads the values of atomic counters starting at `byte_offset`. Counters written by
+    /// shaders need a `MemoryBarrier` before they can be read reliably.
+    pub fn read_atomic_counters(&self, byte_offset: usize, counters: &mut [u32]) -> ReglResult<()> {
+        self.base_buffer.read_data(byte_offset, counters)
+    }
+
+    /// Copies `len` bytes to `destination` without a round trip through client memory. The
+    /// destination may be this same buffer, as long as the ranges don't overlap.
+    pub fn copy_to(&self,
+                   destination: &Buffer,
+                   source_offset: usize,
+                   destination_offset: usize,
+                   len: usize)
+                   -> ReglResult<()> {
+        self.base_buffer.copy_to(&*destination.base_buffer, source_offset, destination_offset, len)
+    }
+
+    /// Changes the size of the buffer to `data_len` bytes. If `preserve_contents` is set, the
+    /// contents are kept up to the smaller of the old and new size; otherwise the contents
+    /// are undefined afterwards. Vertex arrays using the buffer stay valid.
+    ///
+    /// Fails for buffers with immutable storage.
+    pub fn resize(&self, data_len: usize, preserve_contents: bool) -> ReglResult<()> {
+        self.base_buffer.resize(data_len, preserve_contents)
+    }
+
+    /// Maps `len` elements of type `T`, starting at `byte_offset`, into client memory for
+    /// reading. The buffer can't be used otherwise until the returned mapping is dropped.
+    pub fn map_range_read<T: Pod>(&mut self,
+                                  byte_offset: usize,
+                                  len: usize,
+                                  access: MapAccess)
+                                  -> ReglResult<ReadMapping<'_, T>> {
+        mapping::map_range_read(&*self.base_buffer, byte_offset, len, access)
+    }
+
+    /// Like `map_range_read`, but for writing only.
+    pub fn map_range_write<T: Pod>(&mut self,
+                                   byte_offset: usize,
+                                   len: usize,
+                                   access: MapAccess)
+                                   -> ReglResult<WriteMapping<'_, T>> {
+        mapping::map_range_write(&*self.base_buffer, byte_offset, len, access)
+    }
+
+    /// Like `map_range_read`, but for both reading and writing.
+    pub fn map_range_read_write<T: Pod>(&mut self,
+                                        byte_offset: usize,
+                                        len: usize,
+                                        access: MapAccess)
+                                        -> ReglResult<ReadWriteMapping<'_, T>> {
+        mapping::map_range_read_write(&*self.base_buffer, byte_offset, len, access)
+    }
+
+    /// Replaces the storage of the buffer with new storage of the same size, leaving the
+    /// contents undefined. The driver may keep the old storage alive for draws still using
+    /// it, so writing to the buffer afterwards doesn't have to wait for them.
+    ///
+    /// Fails for buffers with immutable storage.
+    pub fn orphan(&self) -> ReglResult<()> {
+        self.base_buffer.orphan()
+    }
 }
 
 pub fn get_base_buffer(buffer: &Buffer) -> &Rc<BaseBuffer> {
     &buffer.base_buffer
 }
 
+/// Creates a buffer with immutable storage (glBufferStorage) of `data_len` bytes.
+pub fn create_immutable_buffer<C: BufferCreationSupport>(support: &mut C,
+                                                         target: BufferTarget,
+                                                         data_len: usize,
+                                                         flags: GLbitfield)
+                                                         -> Buffer {
+    let base_buffer = new_base_buffer(support, target, None);
+    base_buffer.storage(data_len, flags);
+    Buffer { base_buffer: Rc::new(base_buffer) }
+}
+
+fn new_base_buffer<C: BufferCreationSupport>(support: &mut C,
+                                             target: BufferTarget,
+                                             usage: Option<BufferUsage>)
+                                             -> BaseBuffer {
+    let mut gl_id = 0;
+    glcall!(GenBuffers(1, &mut gl_id));
+    BaseBuffer {
+        shared_context: support.get_shared_context(),
+        uid: support.generate_id(),
+        gl_id: gl_id,
+        target: target,
+        usage: usage,
+        default_vertex_array: support.get_default_vertex_array(),
+        data_len: Cell::new(0),
+    }
+}
+
 impl UpdateBuffer for Buffer {
 
 }
@@ -185,6 +613,19 @@ fn gl_target(target: BufferTarget) -> GLenum {
         BufferTarget::VertexBuffer => ::gl::ARRAY_BUFFER,
         BufferTarget::IndexBuffer => ::gl::ELEMENT_ARRAY_BUFFER,
         BufferTarget::UniformBuffer => ::gl::UNIFORM_BUFFER,
+        BufferTarget::ShaderStorageBuffer => ::gl::SHADER_STORAGE_BUFFER,
+        BufferTarget::AtomicCounterBuffer => ::gl::ATOMIC_COUNTER_BUFFER,
+        BufferTarget::CopyReadBuffer => ::gl::COPY_READ_BUFFER,
+        BufferTarget::CopyWriteBuffer => ::gl::COPY_WRITE_BUFFER,
+        BufferTarget::DispatchIndirectBuffer => ::gl::DISPATCH_INDIRECT_BUFFER,
+    }
+}
+
+fn gl_indexed_target(target: IndexedBufferTarget) -> GLenum {
+    match target {
+        IndexedBufferTarget::UniformBuffer => ::gl::UNIFORM_BUFFER,
+        IndexedBufferTarget::ShaderStorageBuffer => ::gl::SHADER_STORAGE_BUFFER,
+        IndexedBufferTarget::AtomicCounterBuffer => ::gl::ATOMIC_COUNTER_BUFFER,
     }
 }
 
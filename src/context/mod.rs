@@ -2,20 +2,27 @@
 pub mod shared;
 
 use std::rc::Rc;
-use gl::types::{GLenum, GLint, GLsizei, GLvoid};
-use self::shared::{SharedContext, new_shared_context};
+use gl::types::{GLenum, GLint, GLintptr, GLsizei, GLvoid};
+use ReglResult;
+use ReglError;
+use self::shared::{SharedContext, ComputeLimits, new_shared_context};
 use id::{Id, IdGenerator, GenerateId};
 use options::{self, RenderOption};
 use resource::ResourceCreationSupport;
-use buffer::BufferCreationSupport;
+use buffer::{self, Buffer, BufferTarget, BufferCreationSupport};
+use sync::MemoryBarrier;
 use framebuffer::{self, Framebuffer, FramebufferInternal};
-use vertex_array::{self, VertexArray, VertexArrayInternal};
-use program::{Program, ProgramCreationSupport, ProgramInternal};
+use vertex_array::{self, VertexArray, VertexArrayInternal, ConstantAttribute};
+use program::{Program, ProgramStages, ProgramCache, ProgramCreationSupport, ProgramInternal,
+              DrawProgram};
 use shader::ShaderCreationSupport;
 
 #[derive(Debug,Clone,Copy)]
 pub enum PrimitiveMode {
     Triangles,
+    /// Patches with the given number of vertices each, for programs with tessellation stages.
+    /// The number must be between 1 and MAX_PATCH_VERTICES.
+    Patches(u32),
 }
 
 #[derive(Debug,Clone,Copy)]
@@ -25,12 +32,23 @@ pub enum IndexType {
     UInt,
 }
 
+impl IndexType {
+    pub fn byte_size(&self) -> u32 {
+        match *self {
+            IndexType::UByte => 1,
+            IndexType::UShort => 2,
+            IndexType::UInt => 4,
+        }
+    }
+}
+
 pub struct Context {
     id_gen: IdGenerator,
     shared_context: Rc<SharedContext>,
     default_framebuffer: Framebuffer,
     default_vertex_array: Rc<VertexArray>,
     validate_shaders: bool,
+    program_cache: Option<ProgramCache>,
 }
 
 impl Context {
@@ -49,6 +67,7 @@ impl Context {
             default_framebuffer: default_framebuffer,
             default_vertex_array: default_vertex_array,
             validate_shaders: true,
+            program_cache: None,
         }
     }
 
@@ -60,46 +79,184 @@ impl Context {
         options::set_option(option)
     }
 
+    /// Sets the cache that programs are loaded from and stored to as binaries.
+    pub fn set_program_cache(&mut self, cache: Option<ProgramCache>) {
+        self.program_cache = cache;
+    }
+
     pub fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
         glcall!(Viewport(x, y, width, height));
     }
 
-    pub fn draw(&self,
-                program: &Program,
-                target: &Framebuffer,
-                vertex_array: &VertexArray,
-                mode: PrimitiveMode,
-                first_vertex: u32,
-                count: u32) {
+    /// Sets the value of a generic vertex attribute for the following draw calls. Applies to
+    /// attributes that neither have an enabled array nor a constant value in the vertex array
+    /// being drawn.
+    pub fn constant_attribute<T: Into<ConstantAttribute>>(&self, index: u32, value: T) {
+        vertex_array::set_constant_attribute(&*self.shared_context, index, &value.into());
+    }
+
+    pub fn draw<P: DrawProgram>(&self,
+                                program: &P,
+                                target: &Framebuffer,
+                                vertex_array: &VertexArray,
+                                mode: PrimitiveMode,
+                                first_vertex: u32,
+                                count: u32)
+                                -> ReglResult<()> {
+        try!(check_draw_stages(try!(program.draw_stages()), mode, &self.shared_context));
         program.bind();
         target.bind();
         vertex_array.bind();
+        set_patch_vertices(mode);
         glcall!(DrawArrays(gl_mode(mode), first_vertex as GLint, count as GLsizei));
+        Ok(())
     }
 
-    pub fn draw_indexed(&self,
-                        program: &Program,
-                        target: &Framebuffer,
-                        vertex_array: &VertexArray,
-                        mode: PrimitiveMode,
-                        index_type: IndexType,
-                        base_vertex: u32,
-                        count: u32,
-                        index_offset: usize) {
+    pub fn draw_indexed<P: DrawProgram>(&self,
+                                        program: &P,
+                                        target: &Framebuffer,
+                                        vertex_array: &VertexArray,
+                                        mode: PrimitiveMode,
+                                        index_type: IndexType,
+                                        base_vertex: u32,
+                                        count: u32,
+                                        index_offset: usize)
+                                        -> ReglResult<()> {
+        try!(check_draw_stages(try!(program.draw_stages()), mode, &self.shared_context));
         program.bind();
         target.bind();
         vertex_array.bind();
+        set_patch_vertices(mode);
         glcall!(DrawElementsBaseVertex(gl_mode(mode),
                                        count as GLsizei,
                                        gl_type(index_type),
                                        index_offset as *const GLvoid,
                                        base_vertex as GLint));
+        Ok(())
+    }
+
+    /// Like `draw_indexed`, but the index type is taken from the vertex array, which must have
+    /// been given an `IndexBuffer`. Draws `count` indices starting from the index at
+    /// `first_index`, and fails if they don't all fit in the index buffer.
+    pub fn draw_elements<P: DrawProgram>(&self,
+                                         program: &P,
+                                         target: &Framebuffer,
+                                         vertex_array: &VertexArray,
+                                         mode: PrimitiveMode,
+                                         base_vertex: u32,
+                                         first_index: u32,
+                                         count: u32)
+                                         -> ReglResult<()> {
+        let (index_type, index_count) = match (vertex_array.index_type(),
+                                               vertex_array.index_count()) {
+            (Some(index_type), Some(index_count)) => (index_type, index_count),
+            _ => return Err(ReglError::UntypedIndexBuffer),
+        };
+        if first_index as usize + count as usize > index_count {
+            return Err(ReglError::IndexRangeOutOfBounds);
+        }
+        self.draw_indexed(program,
+                          target,
+                          vertex_array,
+                          mode,
+                          index_type,
+                          base_vertex,
+                          count,
+                          first_index as usize * index_type.byte_size() as usize)
+    }
+
+    /// Runs the compute program with `x * y * z` work groups. Fails if the program isn't a
+    /// compute program, or if a count or the work group size of the program exceeds the
+    /// implementation limits.
+    pub fn dispatch_compute(&self, program: &Program, x: u32, y: u32, z: u32) -> ReglResult<()> {
+        let limits = self.shared_context.compute_limits();
+        try!(check_compute_program(program, &limits));
+        let max_count = limits.work_group_count;
+        if x > max_count[0] || y > max_count[1] || z > max_count[2] {
+            return Err(ReglError::WorkGroupCountExceeded);
+        }
+        program.bind();
+        glcall!(DispatchCompute(x, y, z));
+        Ok(())
+    }
+
+    /// Runs the compute program with the work group counts read from `buffer` at
+    /// `byte_offset`, as three `u32`s. The counts are only known to the GL, so they can't be
+    /// checked against the limits; the work group size of the program is.
+    pub fn dispatch_compute_indirect(&self,
+                                     program: &Program,
+                                     buffer: &Buffer,
+                                     byte_offset: usize)
+                                     -> ReglResult<()> {
+        try!(check_compute_program(program, &self.shared_context.compute_limits()));
+        if byte_offset % 4 != 0 {
+            return Err(ReglError::MisalignedBufferOffset);
+        }
+        if byte_offset + 12 > buffer.data_len() {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        program.bind();
+        buffer::get_base_buffer(buffer).bind_target(BufferTarget::DispatchIndirectBuffer);
+        glcall!(DispatchComputeIndirect(byte_offset as GLintptr));
+        Ok(())
+    }
+
+    /// Orders memory accesses made by shaders before the barrier against the accesses of the
+    /// given kinds made after it.
+    pub fn memory_barrier(&self, barriers: MemoryBarrier) {
+        glcall!(MemoryBarrier(barriers.bits()));
     }
 }
 
 fn gl_mode(mode: PrimitiveMode) -> GLenum {
     match mode {
         PrimitiveMode::Triangles => ::gl::TRIANGLES,
+        PrimitiveMode::Patches(_) => ::gl::PATCHES,
+    }
+}
+
+/// Programs with tessellation stages can only draw patches, and only they can draw patches.
+/// Patches must have between 1 and MAX_PATCH_VERTICES vertices.
+fn check_draw_stages(stages: ProgramStages,
+                     mode: PrimitiveMode,
+                     shared_context: &SharedContext)
+                     -> ReglResult<()> {
+    let patches = match mode {
+        PrimitiveMode::Patches(_) => true,
+        _ => false,
+    };
+    match stages {
+        ProgramStages::Compute { .. } => return Err(ReglError::ComputeProgramDraw),
+        ProgramStages::Graphics { tessellation, .. } if tessellation != patches => {
+            return Err(ReglError::PatchPrimitiveModeMismatch)
+        }
+        ProgramStages::Graphics { .. } => {}
+    }
+    if let PrimitiveMode::Patches(vertices) = mode {
+        if vertices == 0 || vertices > shared_context.max_patch_vertices() {
+            return Err(ReglError::InvalidPatchVertices);
+        }
+    }
+    Ok(())
+}
+
+fn check_compute_program(program: &Program, limits: &ComputeLimits) -> ReglResult<()> {
+    let size = match program.stages() {
+        ProgramStages::Compute { work_group_size } => work_group_size,
+        ProgramStages::Graphics { .. } => return Err(ReglError::NotComputeProgram),
+    };
+    let max_size = limits.work_group_size;
+    let invocations = size[0] as u64 * size[1] as u64 * size[2] as u64;
+    if size[0] > max_size[0] || size[1] > max_size[1] || size[2] > max_size[2] ||
+       invocations > limits.work_group_invocations as u64 {
+        return Err(ReglError::WorkGroupSizeExceeded);
+    }
+    Ok(())
+}
+
+fn set_patch_vertices(mode: PrimitiveMode) {
+    if let PrimitiveMode::Patches(vertices) = mode {
+        glcall!(PatchParameteri(::gl::PATCH_VERTICES, vertices as GLint));
     }
 }
 
@@ -133,6 +290,14 @@ impl ProgramCreationSupport for Context {
     fn validate_after_linking(&self) -> bool {
         self.validate_shaders
     }
+
+    fn program_cache(&self) -> Option<&ProgramCache> {
+        self.program_cache.as_ref()
+    }
+
+    fn parallel_shader_compile(&self) -> bool {
+        self.shared_context.parallel_shader_compile()
+    }
 }
 
 impl ShaderCreationSupport for Context {
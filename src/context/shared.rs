@@ -1,10 +1,17 @@
 
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use gl::types::{GLenum, GLint};
 use id::Id;
-use tracker::{SimpleTracker, BindIf, BindNone};
+use tracker::{SimpleTracker, IndexedValueTracker, IndexedBinding, BindIf, BindNone, BindIndexedIf,
+              SetIf, UnbindIf};
 use framebuffer::{FramebufferSupport, DrawFramebufferTag};
-use buffer::{BufferSupport, VertexBufferTag, IndexBufferTag, UniformBufferTag};
-use vertex_array::{VertexArray, VertexArraySupport};
-use program::{Program, ProgramSupport};
+use buffer::{BufferSupport, IndexedBufferTarget, VertexBufferTag, IndexBufferTag, UniformBufferTag,
+             ShaderStorageBufferTag, AtomicCounterBufferTag, CopyReadBufferTag,
+             CopyWriteBufferTag, DispatchIndirectBufferTag};
+use vertex_array::{VertexArray, VertexArraySupport, ConstantAttribute};
+use program::{Program, ProgramPipeline, ProgramSupport};
 
 #[derive(Debug)]
 pub struct SharedContext {
@@ -13,7 +20,32 @@ pub struct SharedContext {
     vertex_buffer_tracker: SimpleTracker,
     index_buffer_tracker: SimpleTracker,
     uniform_buffer_tracker: SimpleTracker,
+    shader_storage_buffer_tracker: SimpleTracker,
+    atomic_counter_buffer_tracker: SimpleTracker,
+    copy_read_buffer_tracker: SimpleTracker,
+    copy_write_buffer_tracker: SimpleTracker,
+    dispatch_indirect_buffer_tracker: SimpleTracker,
     program_tracker: SimpleTracker,
+    program_pipeline_tracker: SimpleTracker,
+    constant_attribute_tracker: IndexedValueTracker<ConstantAttribute>,
+    uniform_buffer_bindings: IndexedValueTracker<IndexedBinding>,
+    uniform_buffer_offset_alignment: Cell<Option<GLint>>,
+    shader_storage_buffer_bindings: IndexedValueTracker<IndexedBinding>,
+    shader_storage_buffer_offset_alignment: Cell<Option<GLint>>,
+    atomic_counter_buffer_bindings: IndexedValueTracker<IndexedBinding>,
+    compute_limits: Cell<Option<ComputeLimits>>,
+    max_patch_vertices: Cell<Option<GLint>>,
+    parallel_shader_compile: Cell<Option<bool>>,
+}
+
+#[derive(Debug,Clone,Copy)]
+pub struct ComputeLimits {
+    /// Largest number of work groups a dispatch may have in each dimension.
+    pub work_group_count: [u32; 3],
+    /// Largest size of a work group in each dimension.
+    pub work_group_size: [u32; 3],
+    /// Largest number of invocations in a work group, the product of its dimensions.
+    pub work_group_invocations: u32,
 }
 
 pub fn new_shared_context() -> SharedContext {
@@ -23,7 +55,65 @@ pub fn new_shared_context() -> SharedContext {
         vertex_buffer_tracker: SimpleTracker::new(),
         index_buffer_tracker: SimpleTracker::new(),
         uniform_buffer_tracker: SimpleTracker::new(),
+        shader_storage_buffer_tracker: SimpleTracker::new(),
+        atomic_counter_buffer_tracker: SimpleTracker::new(),
+        copy_read_buffer_tracker: SimpleTracker::new(),
+        copy_write_buffer_tracker: SimpleTracker::new(),
+        dispatch_indirect_buffer_tracker: SimpleTracker::new(),
         program_tracker: SimpleTracker::new(),
+        program_pipeline_tracker: SimpleTracker::new(),
+        constant_attribute_tracker: IndexedValueTracker::new(),
+        uniform_buffer_bindings: IndexedValueTracker::new(),
+        uniform_buffer_offset_alignment: Cell::new(None),
+        shader_storage_buffer_bindings: IndexedValueTracker::new(),
+        shader_storage_buffer_offset_alignment: Cell::new(None),
+        atomic_counter_buffer_bindings: IndexedValueTracker::new(),
+        compute_limits: Cell::new(None),
+        max_patch_vertices: Cell::new(None),
+        parallel_shader_compile: Cell::new(None),
+    }
+}
+
+impl SharedContext {
+    /// Implementation limits of compute dispatches.
+    pub fn compute_limits(&self) -> ComputeLimits {
+        if let Some(limits) = self.compute_limits.get() {
+            return limits;
+        }
+        let indexed = |name| {
+            let mut values = [0; 3];
+            for (index, value) in values.iter_mut().enumerate() {
+                let mut gl_value = 0;
+                glcall!(GetIntegeri_v(name, index as u32, &mut gl_value));
+                *value = gl_value as u32;
+            }
+            values
+        };
+        let mut invocations = 0;
+        glcall!(GetIntegerv(::gl::MAX_COMPUTE_WORK_GROUP_INVOCATIONS, &mut invocations));
+        let limits = ComputeLimits {
+            work_group_count: indexed(::gl::MAX_COMPUTE_WORK_GROUP_COUNT),
+            work_group_size: indexed(::gl::MAX_COMPUTE_WORK_GROUP_SIZE),
+            work_group_invocations: invocations as u32,
+        };
+        self.compute_limits.set(Some(limits));
+        limits
+    }
+
+    /// Largest number of vertices in a patch.
+    pub fn max_patch_vertices(&self) -> u32 {
+        cached_integer(&self.max_patch_vertices, ::gl::MAX_PATCH_VERTICES) as u32
+    }
+
+    /// Whether KHR_parallel_shader_compile, or its ARB predecessor, is supported.
+    pub fn parallel_shader_compile(&self) -> bool {
+        if let Some(supported) = self.parallel_shader_compile.get() {
+            return supported;
+        }
+        let supported = has_extension("GL_KHR_parallel_shader_compile") ||
+                        has_extension("GL_ARB_parallel_shader_compile");
+        self.parallel_shader_compile.set(Some(supported));
+        supported
     }
 }
 
@@ -64,12 +154,90 @@ impl BindIf<UniformBufferTag> for SharedContext {
     }
 }
 
+impl BindIf<ShaderStorageBufferTag> for SharedContext {
+    fn bind_if(&self, uid: &Id, bind: &Fn()) {
+        self.shader_storage_buffer_tracker.bind_if(uid, bind)
+    }
+}
+
+impl BindIf<AtomicCounterBufferTag> for SharedContext {
+    fn bind_if(&self, uid: &Id, bind: &Fn()) {
+        self.atomic_counter_buffer_tracker.bind_if(uid, bind)
+    }
+}
+
+impl BindIf<CopyReadBufferTag> for SharedContext {
+    fn bind_if(&self, uid: &Id, bind: &Fn()) {
+        self.copy_read_buffer_tracker.bind_if(uid, bind)
+    }
+}
+
+impl BindNone<CopyReadBufferTag> for SharedContext {
+    fn bind_none(&self) {
+        self.copy_read_buffer_tracker.bind_none()
+    }
+}
+
+impl BindIf<CopyWriteBufferTag> for SharedContext {
+    fn bind_if(&self, uid: &Id, bind: &Fn()) {
+        self.copy_write_buffer_tracker.bind_if(uid, bind)
+    }
+}
+
+impl BindNone<CopyWriteBufferTag> for SharedContext {
+    fn bind_none(&self) {
+        self.copy_write_buffer_tracker.bind_none()
+    }
+}
+
+impl BindIf<DispatchIndirectBufferTag> for SharedContext {
+    fn bind_if(&self, uid: &Id, bind: &Fn()) {
+        self.dispatch_indirect_buffer_tracker.bind_if(uid, bind)
+    }
+}
+
+impl BindIndexedIf<UniformBufferTag> for SharedContext {
+    fn bind_indexed_if(&self, index: u32, binding: &IndexedBinding, bind: &Fn()) {
+        self.uniform_buffer_bindings.set_if(index, binding, bind)
+    }
+}
+
+impl BindIndexedIf<ShaderStorageBufferTag> for SharedContext {
+    fn bind_indexed_if(&self, index: u32, binding: &IndexedBinding, bind: &Fn()) {
+        self.shader_storage_buffer_bindings.set_if(index, binding, bind)
+    }
+}
+
+impl BindIndexedIf<AtomicCounterBufferTag> for SharedContext {
+    fn bind_indexed_if(&self, index: u32, binding: &IndexedBinding, bind: &Fn()) {
+        self.atomic_counter_buffer_bindings.set_if(index, binding, bind)
+    }
+}
+
 impl BindIf<Program> for SharedContext {
     fn bind_if(&self, uid: &Id, bind: &Fn()) {
         self.program_tracker.bind_if(uid, bind)
     }
 }
 
+impl UnbindIf<Program> for SharedContext {
+    fn unbind_if(&self, unbind: &Fn()) {
+        self.program_tracker.unbind_if(unbind)
+    }
+}
+
+impl BindIf<ProgramPipeline> for SharedContext {
+    fn bind_if(&self, uid: &Id, bind: &Fn()) {
+        self.program_pipeline_tracker.bind_if(uid, bind)
+    }
+}
+
+impl SetIf<ConstantAttribute> for SharedContext {
+    fn set_if(&self, index: u32, value: &ConstantAttribute, set: &Fn()) {
+        self.constant_attribute_tracker.set_if(index, value, set)
+    }
+}
+
 impl FramebufferSupport for SharedContext {}
 
 impl VertexArraySupport for SharedContext {
@@ -78,6 +246,45 @@ impl VertexArraySupport for SharedContext {
     }
 }
 
-impl BufferSupport for SharedContext {}
+impl BufferSupport for SharedContext {
+    fn offset_alignment(&self, target: IndexedBufferTarget) -> usize {
+        let alignment = match target {
+            IndexedBufferTarget::UniformBuffer =>
+                cached_integer(&self.uniform_buffer_offset_alignment,
+                               ::gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT),
+            IndexedBufferTarget::ShaderStorageBuffer =>
+                cached_integer(&self.shader_storage_buffer_offset_alignment,
+                               ::gl::SHADER_STORAGE_BUFFER_OFFSET_ALIGNMENT),
+            // Atomic counters are always 4 bytes and need no query
+            IndexedBufferTarget::AtomicCounterBuffer => 4,
+        };
+        if alignment > 0 {
+            alignment as usize
+        } else {
+            1
+        }
+    }
+}
+
+fn has_extension(name: &str) -> bool {
+    let mut count = 0;
+    glcall!(GetIntegerv(::gl::NUM_EXTENSIONS, &mut count));
+    (0..count as u32).any(|index| {
+        let extension = glcall!(GetStringi(::gl::EXTENSIONS, index));
+        !extension.is_null() &&
+        unsafe { CStr::from_ptr(extension as *const c_char) }.to_bytes() == name.as_bytes()
+    })
+}
+
+/// Implementation limits don't change, so they are queried only once.
+fn cached_integer(cache: &Cell<Option<GLint>>, name: GLenum) -> GLint {
+    if let Some(value) = cache.get() {
+        return value;
+    }
+    let mut value = 0;
+    glcall!(GetIntegerv(name, &mut value));
+    cache.set(Some(value));
+    value
+}
 
 impl ProgramSupport for SharedContext {}
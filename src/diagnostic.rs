@@ -0,0 +1,421 @@
+
+use std::fmt::{self, Display};
+use preprocessor::LineMap;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+/// A single message of a shader compiler or program linker info log.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Index of the source string given to the GL, as reported by the driver.
+    pub source_index: Option<u32>,
+    /// Name of the original file, for shaders compiled from preprocessed source.
+    pub file: Option<String>,
+    /// Line number, 1-based. Refers to the original file if `file` is set.
+    pub line: Option<u32>,
+    /// Column, 1-based. Only some drivers report it.
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+/// An info log of a shader or a program, parsed into diagnostics.
+///
+/// The logs of Mesa (`0:12(5): error: ...`), NVIDIA (`0(12) : error C0000: ...`) and AMD and
+/// Intel (`ERROR: 0:12: ...`) are understood. Lines in other formats continue the message of
+/// the preceding diagnostic, or become diagnostics without a location.
+#[derive(Debug,Clone)]
+pub struct InfoLog {
+    text: String,
+    /// The log with line references mapped to the original files, if it has a line map.
+    remapped_text: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl InfoLog {
+    pub fn parse(text: String) -> InfoLog {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            match parse_line(line) {
+                Some(diagnostic) => {
+                    if !is_summary(&diagnostic) {
+                        diagnostics.push(diagnostic);
+                    }
+                }
+                None => {
+                    match diagnostics.last_mut() {
+                        Some(last) => {
+                            last.message.push('\n');
+                            last.message.push_str(line.trim_end());
+                        }
+                        None => diagnostics.push(unlocated(Severity::Info, line.trim())),
+                    }
+                }
+            }
+        }
+        InfoLog {
+            text: text,
+            remapped_text: None,
+            diagnostics: diagnostics,
+        }
+    }
+
+    /// Parses the log of a shader compiled from preprocessed source, and maps the lines of
+    /// the diagnostics back to the original files.
+    pub fn parse_mapped(text: String, line_map: &LineMap) -> InfoLog {
+        let mut log = InfoLog::parse(text);
+        for diagnostic in &mut log.diagnostics {
+            if let Some((file, line)) = diagnostic.line
+                                                  .and_then(|l| line_map.original_location(l)) {
+                diagnostic.file = Some(file.to_string());
+                diagnostic.line = Some(line);
+            }
+        }
+        log.remapped_text = Some(line_map.remap_log(&log.text));
+        log
+    }
+
+    /// The log as written by the driver.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The log with its line references referring to the original files, for logs parsed with
+    /// `parse_mapped`. Other logs are returned as written by the driver.
+    pub fn remapped_text(&self) -> &str {
+        self.remapped_text.as_ref().unwrap_or(&self.text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn errors<'a>(&'a self) -> Box<Iterator<Item = &'a Diagnostic> + 'a> {
+        self.with_severity(Severity::Error)
+    }
+
+    pub fn warnings<'a>(&'a self) -> Box<Iterator<Item = &'a Diagnostic> + 'a> {
+        self.with_severity(Severity::Warning)
+    }
+
+    /// Renders every diagnostic with an excerpt of `source`, see `Diagnostic::render`.
+    pub fn render(&self, source: &str) -> String {
+        let rendered: Vec<String> = self.diagnostics.iter().map(|d| d.render(source)).collect();
+        rendered.join("\n")
+    }
+
+    fn with_severity<'a>(&'a self,
+                         severity: Severity)
+                         -> Box<Iterator<Item = &'a Diagnostic> + 'a> {
+        Box::new(self.diagnostics.iter().filter(move |d| d.severity == severity))
+    }
+}
+
+/// Displays the remapped text.
+impl Display for InfoLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.remapped_text())
+    }
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic followed by the line of `source` it refers to, with the column
+    /// marked if known. `source` must be the text the line number refers to, i.e. the
+    /// original file for diagnostics with a `file`.
+    pub fn render(&self, source: &str) -> String {
+        let line = match self.line {
+            Some(line) if line > 0 => line,
+            _ => return self.to_string(),
+        };
+        let text = match source.lines().nth(line as usize - 1) {
+            Some(text) => text,
+            None => return self.to_string(),
+        };
+        let number = line.to_string();
+        let gutter = " ".repeat(number.len());
+        let mut rendered = format!("{}\n{} |\n{} | {}\n", self, gutter, number, text);
+        if let Some(column) = self.column {
+            // Keep tabs so that the marker lines up with the excerpt
+            let indent: String = text.chars()
+                                     .take(column.saturating_sub(1) as usize)
+                                     .map(|c| if c == '\t' { '\t' } else { ' ' })
+                                     .collect();
+            rendered.push_str(&format!("{} | {}^\n", gutter, indent));
+        }
+        rendered
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let source = match (&self.file, self.source_index) {
+            (&Some(ref file), _) => Some(file.clone()),
+            (&None, Some(index)) => Some(index.to_string()),
+            (&None, None) => None,
+        };
+        match (source, self.line, self.column) {
+            (Some(source), Some(line), Some(column)) =>
+                try!(write!(f, "{}:{}:{}: ", source, line, column)),
+            (Some(source), Some(line), None) => try!(write!(f, "{}:{}: ", source, line)),
+            _ => {}
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn parse_line(line: &str) -> Option<Diagnostic> {
+    let line = line.trim();
+    // AMD and Intel: "ERROR: 0:12: message", or a message without location
+    if let Some((severity, rest)) = leading_severity(line) {
+        let rest = rest.trim_start();
+        if let Some((source_index, rest)) = number(rest) {
+            if rest.starts_with(':') {
+                if let Some((line_number, rest)) = number(&rest[1..]) {
+                    if rest.starts_with(':') {
+                        return Some(Diagnostic {
+                            severity: severity,
+                            source_index: Some(source_index),
+                            file: None,
+                            line: Some(line_number),
+                            column: None,
+                            message: rest[1..].trim().to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        return Some(unlocated(severity, rest.trim()));
+    }
+
+    let (source_index, rest) = match number(line) {
+        Some(parsed) => parsed,
+        None => return None,
+    };
+    let (line_number, column, rest) = if rest.starts_with(':') {
+        // Mesa: "0:12(5): error: message"
+        let (line_number, rest) = match number(&rest[1..]) {
+            Some(parsed) => parsed,
+            None => return None,
+        };
+        let (column, rest) = match parenthesized_number(rest) {
+            Some((column, rest)) => (Some(column), rest),
+            None => (None, rest),
+        };
+        (line_number, column, rest)
+    } else {
+        // NVIDIA: "0(12) : error C0000: message"
+        match parenthesized_number(rest) {
+            Some((line_number, rest)) => (line_number, None, rest),
+            None => return None,
+        }
+    };
+    let rest = rest.trim_start();
+    if !rest.starts_with(':') {
+        return None;
+    }
+    let rest = &rest[1..];
+    let (label, message) = match rest.find(':') {
+        Some(colon) => (&rest[..colon], &rest[colon + 1..]),
+        None => ("", rest),
+    };
+    let severity = match label_severity(label) {
+        Some(severity) => severity,
+        None => return None,
+    };
+    Some(Diagnostic {
+        severity: severity,
+        source_index: Some(source_index),
+        file: None,
+        line: Some(line_number),
+        column: column,
+        message: message.trim().to_string(),
+    })
+}
+
+/// "error: ", "WARNING: ", "link error: " and the like at the start of a line.
+fn leading_severity(line: &str) -> Option<(Severity, &str)> {
+    let colon = match line.find(':') {
+        Some(colon) => colon,
+        None => return None,
+    };
+    label_severity(&line[..colon]).map(|severity| (severity, &line[colon + 1..]))
+}
+
+/// Severity from labels like "error", "preprocessor error" or "warning C7050".
+fn label_severity(label: &str) -> Option<Severity> {
+    let label = label.to_lowercase();
+    if label.contains("error") {
+        Some(Severity::Error)
+    } else if label.contains("warning") {
+        Some(Severity::Warning)
+    } else if label.contains("info") || label.contains("note") {
+        Some(Severity::Info)
+    } else {
+        None
+    }
+}
+
+fn number(text: &str) -> Option<(u32, &str)> {
+    let end = text.find(|c: char| !c.is_digit(10)).unwrap_or(text.len());
+    match text[..end].parse() {
+        Ok(value) => Some((value, &text[end..])),
+        Err(_) => None,
+    }
+}
+
+fn parenthesized_number(text: &str) -> Option<(u32, &str)> {
+    if !text.starts_with('(') {
+        return None;
+    }
+    match number(&text[1..]) {
+        Some((value, rest)) if rest.starts_with(')') => Some((value, &rest[1..])),
+        _ => None,
+    }
+}
+
+/// AMD ends its logs with a count of the errors, which adds nothing to the diagnostics.
+fn is_summary(diagnostic: &Diagnostic) -> bool {
+    diagnostic.line.is_none() && diagnostic.message.ends_with("No code generated.")
+}
+
+fn unlocated(severity: Severity, message: &str) -> Diagnostic {
+    Diagnostic {
+        severity: severity,
+        source_index: None,
+        file: None,
+        line: None,
+        column: None,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use preprocessor::{ShaderPreprocessor, VirtualIncludeProvider};
+    use super::{InfoLog, Diagnostic, Severity};
+
+    fn located(severity: Severity, line: u32, column: Option<u32>, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: severity,
+            source_index: Some(0),
+            file: None,
+            line: Some(line),
+            column: column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_mesa_log() {
+        let log = InfoLog::parse("0:12(3): error: `x' undeclared\n\
+                                  0:14(10): warning: unused variable\n"
+                                     .to_string());
+        assert_eq!(log.diagnostics(),
+                   &[located(Severity::Error, 12, Some(3), "`x' undeclared"),
+                     located(Severity::Warning, 14, Some(10), "unused variable")]);
+        assert_eq!(log.errors().count(), 1);
+        assert_eq!(log.warnings().count(), 1);
+    }
+
+    #[test]
+    fn parses_nvidia_log() {
+        let log = InfoLog::parse("0(12) : error C1008: undefined variable \"x\"\n\
+                                  0(20) : warning C7050: \"y\" might be used before being \
+                                  initialized"
+                                     .to_string());
+        assert_eq!(log.diagnostics(),
+                   &[located(Severity::Error, 12, None, "undefined variable \"x\""),
+                     located(Severity::Warning,
+                             20,
+                             None,
+                             "\"y\" might be used before being initialized")]);
+    }
+
+    #[test]
+    fn parses_amd_and_intel_log() {
+        let log = InfoLog::parse("ERROR: 0:12: 'x' : undeclared identifier\n\
+                                  WARNING: 0:3: extension not supported\n\
+                                  ERROR: 1 compilation errors.  No code generated.\n"
+                                     .to_string());
+        assert_eq!(log.diagnostics(),
+                   &[located(Severity::Error, 12, None, "'x' : undeclared identifier"),
+                     located(Severity::Warning, 3, None, "extension not supported")]);
+    }
+
+    #[test]
+    fn continues_messages_and_keeps_unlocated_lines() {
+        let log = InfoLog::parse("Linking failed\n\
+                                  0:4(1): error: syntax error\n  near `}'\n"
+                                     .to_string());
+        let diagnostics = log.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, Severity::Info);
+        assert_eq!(diagnostics[0].line, None);
+        assert_eq!(diagnostics[0].message, "Linking failed");
+        assert_eq!(diagnostics[1].message, "syntax error\n  near `}'");
+    }
+
+    #[test]
+    fn parses_severity_labels_with_spaces() {
+        let log = InfoLog::parse("link error: out of resources\n".to_string());
+        assert_eq!(log.errors().count(), 1);
+        assert_eq!(log.diagnostics()[0].severity, Severity::Error);
+        assert_eq!(log.diagnostics()[0].line, None);
+        assert_eq!(log.diagnostics()[0].message, "out of resources");
+    }
+
+    #[test]
+    fn parses_unlocated_severity_lines() {
+        let log = InfoLog::parse("error: no main function\n".to_string());
+        assert_eq!(log.diagnostics().len(), 1);
+        assert_eq!(log.diagnostics()[0].severity, Severity::Error);
+        assert_eq!(log.diagnostics()[0].line, None);
+        assert_eq!(log.diagnostics()[0].message, "no main function");
+    }
+
+    #[test]
+    fn maps_diagnostics_to_original_files() {
+        let mut provider = VirtualIncludeProvider::new();
+        provider.add("a.glsl", "float a;\nfloat b;");
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .preprocess("main", "#include \"a.glsl\"\nvoid main() {}")
+                               .unwrap();
+        let text = "0:2(5): error: oops\n0:3(1): warning: hmm";
+        let log = InfoLog::parse_mapped(text.to_string(), preprocessed.line_map());
+        assert_eq!(log.text(), text);
+        assert_eq!(log.remapped_text(), "a.glsl:2(5): error: oops\nmain:2(1): warning: hmm");
+        assert_eq!(log.diagnostics()[0].file, Some("a.glsl".to_string()));
+        assert_eq!(log.diagnostics()[0].line, Some(2));
+        assert_eq!(log.diagnostics()[1].file, Some("main".to_string()));
+        assert_eq!(log.diagnostics()[1].line, Some(2));
+        assert_eq!(log.diagnostics()[0].to_string(), "a.glsl:2:5: error: oops");
+    }
+
+    #[test]
+    fn renders_excerpt_with_column_marker() {
+        let diagnostic = located(Severity::Error, 2, Some(3), "bad");
+        assert_eq!(diagnostic.render("line one\n\tx = 1;\n"),
+                   "0:2:3: error: bad\n  |\n2 | \tx = 1;\n  | \t ^\n");
+    }
+}
@@ -2,15 +2,50 @@
 use std::error::Error;
 use std::fmt::{self, Display};
 use std::ffi::NulError;
+use diagnostic::InfoLog;
 
 #[derive(Debug)]
 pub enum ReglError {
     NulError(NulError),
-    ShaderCompilationError(String),
-    ProgramLinkingError(String),
+    ShaderCompilationError(InfoLog),
+    ProgramLinkingError(InfoLog),
     BufferDataOutOfRange,
     UniformTypeMismatch,
     InvalidUniformValueCount,
+    UnknownVertexAttribute,
+    UnresolvedAttributeName(String),
+    InactiveAttribute(String),
+    AttributeTypeMismatch(String),
+    UntypedIndexBuffer,
+    IndexRangeOutOfBounds,
+    MisalignedBufferOffset,
+    InvalidMapRange,
+    BufferMappingFailed,
+    BufferDataCorrupted,
+    InvalidMapAccess,
+    ImmutableBufferStorage,
+    StreamBufferFull,
+    OverlappingBufferCopy,
+    InactiveUniformBlock(String),
+    InactiveStorageBlock(String),
+    OutOfBufferSpace,
+    InvalidShaderStages(String),
+    ComputeProgramDraw,
+    PatchPrimitiveModeMismatch,
+    NotComputeProgram,
+    WorkGroupCountExceeded,
+    IncludeNotFound(String),
+    RecursiveInclude(String),
+    MalformedInclude(String),
+    ShaderSourceUnreadable(String),
+    ProgramNotSeparable,
+    InactiveUniform(String),
+    UniformArraySizeExceeded(String),
+    FenceWaitFailed,
+    WorkGroupSizeExceeded,
+    ComputeProgramInPipeline,
+    MissingVertexStage,
+    InvalidPatchVertices,
 }
 
 impl From<NulError> for ReglError {
@@ -39,6 +74,67 @@ impl Error for ReglError {
             ReglError::InvalidUniformValueCount =>
                 "The length of uniform value slice, the UniformType and the count parameter do not \
                  match ",
+            ReglError::UnknownVertexAttribute =>
+                "The vertex array has no attribute set up at the given index",
+            ReglError::UnresolvedAttributeName(_) =>
+                "Vertex attribute is bound by name, but no program was given to resolve it",
+            ReglError::InactiveAttribute(_) => "The program has no active attribute of that name",
+            ReglError::AttributeTypeMismatch(_) =>
+                "Vertex attribute format doesn't match the type declared in the shader",
+            ReglError::UntypedIndexBuffer =>
+                "The vertex array has no IndexBuffer to take the index type from",
+            ReglError::IndexRangeOutOfBounds => "Tried to draw indices beyond index buffer end",
+            ReglError::MisalignedBufferOffset =>
+                "Buffer offset is not properly aligned for the element type",
+            ReglError::InvalidMapRange =>
+                "Buffer mapping must be non-empty, readable or writable, and flushed only if \
+                 mapped for explicit flushing",
+            ReglError::BufferMappingFailed => "Mapping the buffer range failed",
+            ReglError::BufferDataCorrupted => "Buffer contents were lost while it was mapped",
+            ReglError::InvalidMapAccess =>
+                "The buffer mapping flags are not allowed with that kind of access",
+            ReglError::ImmutableBufferStorage =>
+                "Storage of the buffer is immutable and can't be re-specified or updated",
+            ReglError::StreamBufferFull =>
+                "Data pushed during the frame does not fit in the stream buffer",
+            ReglError::OverlappingBufferCopy =>
+                "Source and destination ranges of a copy within a buffer overlap",
+            ReglError::InactiveUniformBlock(_) =>
+                "The program has no active uniform block of that name",
+            ReglError::InactiveStorageBlock(_) =>
+                "The program has no active shader storage block of that name",
+            ReglError::OutOfBufferSpace =>
+                "No free range of the buffer is large enough for the allocation",
+            ReglError::InvalidShaderStages(_) =>
+                "The shaders do not form a valid combination of program stages",
+            ReglError::ComputeProgramDraw =>
+                "A compute program can only be dispatched, not used for drawing",
+            ReglError::PatchPrimitiveModeMismatch =>
+                "Programs with tessellation stages must draw patches, other programs must not",
+            ReglError::NotComputeProgram =>
+                "Only a program with a compute shader can be dispatched",
+            ReglError::WorkGroupCountExceeded =>
+                "Number of work groups exceeds MAX_COMPUTE_WORK_GROUP_COUNT",
+            ReglError::IncludeNotFound(_) => "The include provider has no file of that name",
+            ReglError::RecursiveInclude(_) =>
+                "File includes itself, directly or through other files",
+            ReglError::MalformedInclude(_) =>
+                "Include directive must be of the form #include \"name\" or #include <name>",
+            ReglError::ShaderSourceUnreadable(_) => "Reading a shader source file failed",
+            ReglError::ProgramNotSeparable =>
+                "Only separable programs can be used in a program pipeline",
+            ReglError::InactiveUniform(_) => "The program has no active uniform of that name",
+            ReglError::UniformArraySizeExceeded(_) =>
+                "More values were given than the uniform array has elements",
+            ReglError::FenceWaitFailed =>
+                "Waiting for a fence failed; the context may have been lost",
+            ReglError::WorkGroupSizeExceeded =>
+                "The work group size of the compute program exceeds the implementation limits",
+            ReglError::ComputeProgramInPipeline =>
+                "A compute program can't be used in a program pipeline",
+            ReglError::MissingVertexStage => "The program pipeline has no vertex stage",
+            ReglError::InvalidPatchVertices =>
+                "Number of patch vertices must be between 1 and MAX_PATCH_VERTICES",
         }
     }
 }
@@ -46,10 +142,44 @@ impl Error for ReglError {
 fn additional_message(error: &ReglError) -> Option<&str> {
     match *error {
         ReglError::NulError(ref error) => Some(error.description()),
-        ReglError::ShaderCompilationError(ref msg) => Some(msg.as_ref()),
-        ReglError::ProgramLinkingError(ref msg) => Some(msg.as_ref()),
+        ReglError::ShaderCompilationError(ref log) => Some(log.remapped_text()),
+        ReglError::ProgramLinkingError(ref log) => Some(log.text()),
         ReglError::BufferDataOutOfRange => None,
         ReglError::UniformTypeMismatch => None,
         ReglError::InvalidUniformValueCount => None,
+        ReglError::UnknownVertexAttribute => None,
+        ReglError::UnresolvedAttributeName(ref name) => Some(name.as_ref()),
+        ReglError::InactiveAttribute(ref name) => Some(name.as_ref()),
+        ReglError::AttributeTypeMismatch(ref msg) => Some(msg.as_ref()),
+        ReglError::UntypedIndexBuffer => None,
+        ReglError::IndexRangeOutOfBounds => None,
+        ReglError::MisalignedBufferOffset => None,
+        ReglError::InvalidMapRange => None,
+        ReglError::BufferMappingFailed => None,
+        ReglError::BufferDataCorrupted => None,
+        ReglError::InvalidMapAccess => None,
+        ReglError::ImmutableBufferStorage => None,
+        ReglError::StreamBufferFull => None,
+        ReglError::OverlappingBufferCopy => None,
+        ReglError::InactiveUniformBlock(ref name) => Some(name.as_ref()),
+        ReglError::InactiveStorageBlock(ref name) => Some(name.as_ref()),
+        ReglError::OutOfBufferSpace => None,
+        ReglError::InvalidShaderStages(ref msg) => Some(msg.as_ref()),
+        ReglError::ComputeProgramDraw => None,
+        ReglError::PatchPrimitiveModeMismatch => None,
+        ReglError::NotComputeProgram => None,
+        ReglError::WorkGroupCountExceeded => None,
+        ReglError::IncludeNotFound(ref name) => Some(name.as_ref()),
+        ReglError::RecursiveInclude(ref name) => Some(name.as_ref()),
+        ReglError::MalformedInclude(ref line) => Some(line.as_ref()),
+        ReglError::ShaderSourceUnreadable(ref msg) => Some(msg.as_ref()),
+        ReglError::ProgramNotSeparable => None,
+        ReglError::InactiveUniform(ref name) => Some(name.as_ref()),
+        ReglError::UniformArraySizeExceeded(ref name) => Some(name.as_ref()),
+        ReglError::FenceWaitFailed => None,
+        ReglError::WorkGroupSizeExceeded => None,
+        ReglError::ComputeProgramInPipeline => None,
+        ReglError::MissingVertexStage => None,
+        ReglError::InvalidPatchVertices => None,
     }
 }
@@ -1,5 +1,6 @@
 
 extern crate gl;
+extern crate regl_derive;
 
 #[macro_use]
 mod macros;
@@ -8,11 +9,20 @@ mod error;
 mod id;
 mod tracker;
 mod resource;
+mod pod;
 mod context;
 mod options;
 mod buffer;
+mod typed_buffer;
+mod mapping;
+mod sync;
+mod stream_buffer;
+mod allocator;
 mod framebuffer;
 mod vertex_array;
+mod vertex;
+mod preprocessor;
+mod diagnostic;
 mod shader;
 mod program;
 
@@ -24,10 +34,25 @@ pub use gl::load_with;
 pub use error::ReglError;
 pub use context::{Context, PrimitiveMode, IndexType};
 pub use options::RenderOption;
-pub use buffer::{Buffer, BufferTarget, BufferUsage};
+pub use pod::Pod;
+pub use buffer::{Buffer, BufferTarget, IndexedBufferTarget, BufferUsage};
+pub use mapping::{MapAccess, ReadMapping, WriteMapping, ReadWriteMapping};
+pub use sync::{Fence, MemoryBarrier};
+pub use stream_buffer::{StreamBuffer, StreamRange};
+pub use allocator::{BufferAllocator, BufferSlice};
+pub use typed_buffer::{TypedBuffer, IndexBuffer, IndexElement};
 pub use framebuffer::Framebuffer;
-pub use vertex_array::{VertexArray, VertexAttributeType, VertexAttribute};
+pub use vertex_array::{VertexArray, VertexAttributeType, VertexAttribute, NamedVertexAttribute,
+                       ConstantAttribute};
+pub use vertex::{Vertex, VertexField, VertexLocation, VertexComponent, IntegerVertexComponent};
+pub use regl_derive::{Vertex, Pod};
 pub use shader::{Shader, ShaderType, ShaderSource};
-pub use program::Program;
+pub use preprocessor::{ShaderPreprocessor, PreprocessedSource, LineMap, IncludeProvider,
+                       FileIncludeProvider, VirtualIncludeProvider};
+pub use diagnostic::{InfoLog, Diagnostic, Severity};
+pub use program::{Program, ProgramBuilder, ProgramStages, ProgramCache};
+pub use program::{ReloadableProgram, ReloadStatus, PendingProgram, ProgramPipeline, DrawProgram};
 pub use program::{AttributeInfo, ShaderAttribute, ShaderAttributeType};
-pub use program::{UniformInfo, Uniform, InterfaceBlock, BlockUniform, UniformType};
+pub use program::{UniformInfo, Uniform, InterfaceBlock, BlockUniform, UniformType,
+                  AtomicCounterBuffer, UniformValue, UniformElement, UniformCacheStats};
+pub use program::{StorageBlockInfo, StorageBlock, BufferVariable};
@@ -0,0 +1,245 @@
+use std::mem::{align_of, size_of};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use gl::types::GLbitfield;
+use ReglResult;
+use ReglError;
+use pod::Pod;
+use buffer::BaseBuffer;
+
+/// Optional flags of a buffer mapping. Whether the range is read or written depends on the
+/// kind of mapping; see `Buffer::map_range_read` and its siblings. See glMapBufferRange for the
+/// meaning of the flags.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct MapAccess {
+    /// GL_MAP_INVALIDATE_RANGE_BIT: the previous contents of the range may be discarded.
+    /// Not allowed when reading.
+    pub invalidate_range: bool,
+    /// GL_MAP_INVALIDATE_BUFFER_BIT: the previous contents of the whole buffer may be
+    /// discarded. Not allowed when reading.
+    pub invalidate_buffer: bool,
+    /// GL_MAP_UNSYNCHRONIZED_BIT: don't wait for pending draws that use the buffer. Not
+    /// allowed when reading.
+    pub unsynchronized: bool,
+    /// GL_MAP_FLUSH_EXPLICIT_BIT: modifications are only guaranteed to be seen by the GL
+    /// after `flush_range`. Requires writing.
+    pub explicit_flush: bool,
+}
+
+impl MapAccess {
+    fn gl_access(&self, read: bool, write: bool) -> GLbitfield {
+        let mut access = 0;
+        let flags = [(read, ::gl::MAP_READ_BIT),
+                     (write, ::gl::MAP_WRITE_BIT),
+                     (self.invalidate_range, ::gl::MAP_INVALIDATE_RANGE_BIT),
+                     (self.invalidate_buffer, ::gl::MAP_INVALIDATE_BUFFER_BIT),
+                     (self.unsynchronized, ::gl::MAP_UNSYNCHRONIZED_BIT),
+                     (self.explicit_flush, ::gl::MAP_FLUSH_EXPLICIT_BIT)];
+        for &(enabled, bit) in &flags {
+            if enabled {
+                access |= bit;
+            }
+        }
+        access
+    }
+}
+
+/// A buffer range mapped for reading. Derefs to the mapped elements, and unmaps the buffer
+/// when dropped.
+#[derive(Debug)]
+pub struct ReadMapping<'a, T: Pod> {
+    range: MappedRange<'a, T>,
+}
+
+/// A buffer range mapped for writing only. The elements are written with `write`. Unmaps the
+/// buffer when dropped.
+///
+/// Unlike the other mappings, this doesn't deref to a slice: without GL_MAP_READ_BIT the
+/// contents of the mapped memory are undefined, and even `&mut [T]` would let safe code read
+/// them. Map with `map_range_read_write` for a mapping that derefs to the elements.
+#[derive(Debug)]
+pub struct WriteMapping<'a, T: Pod> {
+    range: MappedRange<'a, T>,
+}
+
+/// A buffer range mapped for reading and writing. Derefs mutably to the mapped elements, and
+/// unmaps the buffer when dropped.
+#[derive(Debug)]
+pub struct ReadWriteMapping<'a, T: Pod> {
+    range: MappedRange<'a, T>,
+}
+
+#[derive(Debug)]
+struct MappedRange<'a, T: Pod> {
+    buffer: &'a BaseBuffer,
+    ptr: *mut T,
+    len: usize,
+    explicit_flush: bool,
+}
+
+pub fn map_range_read<T: Pod>(buffer: &BaseBuffer,
+                              byte_offset: usize,
+                              len: usize,
+                              access: MapAccess)
+                              -> ReglResult<ReadMapping<'_, T>> {
+    let range = try!(map_range(buffer, byte_offset, len, true, false, access));
+    Ok(ReadMapping { range: range })
+}
+
+pub fn map_range_write<T: Pod>(buffer: &BaseBuffer,
+                               byte_offset: usize,
+                               len: usize,
+                               access: MapAccess)
+                               -> ReglResult<WriteMapping<'_, T>> {
+    let range = try!(map_range(buffer, byte_offset, len, false, true, access));
+    Ok(WriteMapping { range: range })
+}
+
+pub fn map_range_read_write<T: Pod>(buffer: &BaseBuffer,
+                                    byte_offset: usize,
+                                    len: usize,
+                                    access: MapAccess)
+                                    -> ReglResult<ReadWriteMapping<'_, T>> {
+    let range = try!(map_range(buffer, byte_offset, len, true, true, access));
+    Ok(ReadWriteMapping { range: range })
+}
+
+fn map_range<T: Pod>(buffer: &BaseBuffer,
+                     byte_offset: usize,
+                     len: usize,
+                     read: bool,
+                     write: bool,
+                     access: MapAccess)
+                     -> ReglResult<MappedRange<'_, T>> {
+    let byte_len = len * size_of::<T>();
+    if byte_offset + byte_len > buffer.data_len() {
+        return Err(ReglError::BufferDataOutOfRange);
+    }
+    if byte_offset % align_of::<T>() != 0 {
+        return Err(ReglError::MisalignedBufferOffset);
+    }
+    if byte_len == 0 {
+        return Err(ReglError::InvalidMapRange);
+    }
+    // The GL raises INVALID_OPERATION for these
+    let discards = access.invalidate_range || access.invalidate_buffer;
+    if read && (discards || access.unsynchronized) {
+        return Err(ReglError::InvalidMapAccess);
+    }
+    if !write && access.explicit_flush {
+        return Err(ReglError::InvalidMapAccess);
+    }
+    let ptr = buffer.map_range(byte_offset, byte_len, access.gl_access(read, write)) as *mut T;
+    if ptr.is_null() {
+        return Err(ReglError::BufferMappingFailed);
+    }
+    Ok(MappedRange {
+        buffer: buffer,
+        ptr: ptr,
+        len: len,
+        explicit_flush: access.explicit_flush,
+    })
+}
+
+impl<'a, T: Pod> MappedRange<'a, T> {
+    fn flush_range(&self, first_element: usize, len: usize) -> ReglResult<()> {
+        if !self.explicit_flush {
+            return Err(ReglError::InvalidMapRange);
+        }
+        if first_element + len > self.len {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        self.buffer.flush_mapped_range(first_element * size_of::<T>(), len * size_of::<T>());
+        Ok(())
+    }
+
+    fn unmap(self) -> ReglResult<()> {
+        let intact = self.buffer.unmap();
+        ::std::mem::forget(self);
+        if intact {
+            Ok(())
+        } else {
+            Err(ReglError::BufferDataCorrupted)
+        }
+    }
+}
+
+impl<'a, T: Pod> Drop for MappedRange<'a, T> {
+    fn drop(&mut self) {
+        self.buffer.unmap();
+    }
+}
+
+impl<'a, T: Pod> ReadMapping<'a, T> {
+    /// Unmaps the buffer. Unlike dropping the mapping, reports if the contents of the buffer
+    /// were lost while it was mapped (which may happen e.g. on a screen mode change).
+    pub fn unmap(self) -> ReglResult<()> {
+        self.range.unmap()
+    }
+}
+
+impl<'a, T: Pod> Deref for ReadMapping<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.range.ptr, self.range.len) }
+    }
+}
+
+impl<'a, T: Pod> WriteMapping<'a, T> {
+    /// Number of mapped elements.
+    pub fn len(&self) -> usize {
+        self.range.len
+    }
+
+    /// Writes `values` to the mapped elements starting from the element at `first_element`.
+    pub fn write(&mut self, first_element: usize, values: &[T]) -> ReglResult<()> {
+        if first_element + values.len() > self.range.len {
+            return Err(ReglError::BufferDataOutOfRange);
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(),
+                                     self.range.ptr.offset(first_element as isize),
+                                     values.len());
+        }
+        Ok(())
+    }
+
+    /// Makes the modifications to `len` elements starting from the element at `first_element`
+    /// visible to the GL. Requires `MapAccess::explicit_flush`.
+    pub fn flush_range(&self, first_element: usize, len: usize) -> ReglResult<()> {
+        self.range.flush_range(first_element, len)
+    }
+
+    /// See `ReadMapping::unmap`.
+    pub fn unmap(self) -> ReglResult<()> {
+        self.range.unmap()
+    }
+}
+
+impl<'a, T: Pod> ReadWriteMapping<'a, T> {
+    /// See `WriteMapping::flush_range`.
+    pub fn flush_range(&self, first_element: usize, len: usize) -> ReglResult<()> {
+        self.range.flush_range(first_element, len)
+    }
+
+    /// See `ReadMapping::unmap`.
+    pub fn unmap(self) -> ReglResult<()> {
+        self.range.unmap()
+    }
+}
+
+impl<'a, T: Pod> Deref for ReadWriteMapping<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.range.ptr, self.range.len) }
+    }
+}
+
+impl<'a, T: Pod> DerefMut for ReadWriteMapping<'a, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.range.ptr, self.range.len) }
+    }
+}
@@ -0,0 +1,20 @@
+
+/// Marker for "plain old data": types that can be copied to and from GPU memory byte for byte.
+///
+/// A `Pod` type must be `Copy`, contain no pointers or references, have no padding bytes and
+/// accept any bit pattern as a valid value. Use `#[derive(Pod)]` for `#[repr(C)]` structs;
+/// it checks these requirements at compile time.
+pub unsafe trait Pod : Copy + 'static {}
+
+unsafe impl Pod for u8 {}
+unsafe impl Pod for i8 {}
+unsafe impl Pod for u16 {}
+unsafe impl Pod for i16 {}
+unsafe impl Pod for u32 {}
+unsafe impl Pod for i32 {}
+unsafe impl Pod for u64 {}
+unsafe impl Pod for i64 {}
+unsafe impl Pod for f32 {}
+unsafe impl Pod for f64 {}
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
@@ -0,0 +1,522 @@
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+use ReglResult;
+use ReglError;
+
+/// Loads the sources of files named in `#include` directives.
+pub trait IncludeProvider : Debug {
+    /// Returns the source of the named file, or None if there is no such file.
+    fn load(&self, name: &str) -> Option<String>;
+}
+
+/// Loads included files from the file system, resolving names relative to a root directory.
+#[derive(Debug,Clone)]
+pub struct FileIncludeProvider {
+    root: PathBuf,
+}
+
+impl FileIncludeProvider {
+    pub fn new<P: Into<PathBuf>>(root: P) -> FileIncludeProvider {
+        FileIncludeProvider { root: root.into() }
+    }
+}
+
+impl IncludeProvider for FileIncludeProvider {
+    fn load(&self, name: &str) -> Option<String> {
+        fs::read_to_string(self.root.join(name)).ok()
+    }
+}
+
+/// Serves included files from memory, e.g. sources embedded with `include_str!`.
+#[derive(Debug,Clone,Default)]
+pub struct VirtualIncludeProvider {
+    files: HashMap<String, String>,
+}
+
+impl VirtualIncludeProvider {
+    pub fn new() -> VirtualIncludeProvider {
+        VirtualIncludeProvider { files: HashMap::new() }
+    }
+
+    pub fn add<N: Into<String>, S: Into<String>>(&mut self, name: N, source: S) {
+        self.files.insert(name.into(), source.into());
+    }
+}
+
+impl IncludeProvider for VirtualIncludeProvider {
+    fn load(&self, name: &str) -> Option<String> {
+        self.files.get(name).cloned()
+    }
+}
+
+/// Prepares GLSL source for compilation.
+///
+/// `#include "name"` and `#include <name>` are replaced with the source loaded from the
+/// include provider, recursively. A file containing `#pragma once` is included only once.
+/// Includes are resolved before the GLSL preprocessor runs, so they are not affected by
+/// `#if`s.
+///
+/// The result starts with the `#version` directive, followed by the `#extension` directives
+/// found in the sources and the configured defines, as GLSL requires them before any code.
+/// `#extension` directives within `#if`s are left in place, so that they stay conditional.
+#[derive(Debug)]
+pub struct ShaderPreprocessor<'a> {
+    include_provider: Option<&'a IncludeProvider>,
+    version: Option<String>,
+    extensions: Vec<String>,
+    defines: Vec<(String, String)>,
+}
+
+/// Preprocessed GLSL source, along with where each of its lines came from. Compile it with
+/// `Shader::from_preprocessed` to have errors refer to the original files.
+#[derive(Debug,Clone)]
+pub struct PreprocessedSource {
+    source: String,
+    line_map: LineMap,
+}
+
+/// Maps lines of preprocessed source back to the files and lines they came from.
+#[derive(Debug,Clone)]
+pub struct LineMap {
+    files: Vec<String>,
+    /// Index into `files` and line number there, for every line of the preprocessed source.
+    /// None for lines generated by the preprocessor.
+    lines: Vec<Option<(usize, u32)>>,
+}
+
+#[derive(Debug)]
+struct Expansion {
+    files: Vec<String>,
+    lines: Vec<Option<(usize, u32)>>,
+    body: Vec<String>,
+    version: Option<(String, (usize, u32))>,
+    extensions: Vec<(String, (usize, u32))>,
+    include_stack: Vec<String>,
+    included_once: HashSet<String>,
+    /// How many `#if`s the current line is nested in.
+    conditional_depth: usize,
+}
+
+impl<'a> ShaderPreprocessor<'a> {
+    pub fn new() -> ShaderPreprocessor<'a> {
+        ShaderPreprocessor {
+            include_provider: None,
+            version: None,
+            extensions: vec![],
+            defines: vec![],
+        }
+    }
+
+    pub fn include_provider(mut self, provider: &'a IncludeProvider) -> Self {
+        self.include_provider = Some(provider);
+        self
+    }
+
+    /// Overrides the `#version` of the source, e.g. `"330 core"`.
+    pub fn version<T: Into<String>>(mut self, version: T) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Adds an `#extension` directive, e.g. `extension("GL_ARB_shader_draw_parameters",
+    /// "require")`.
+    pub fn extension<N: AsRef<str>, B: AsRef<str>>(mut self, name: N, behavior: B) -> Self {
+        self.extensions.push(format!("#extension {} : {}", name.as_ref(), behavior.as_ref()));
+        self
+    }
+
+    /// Adds `#define name value` after the `#version` and `#extension` directives.
+    pub fn define<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.defines.push((name.into(), value.into()));
+        self
+    }
+
+    /// Preprocesses `source`. `name` identifies it in the line map and error messages.
+    pub fn preprocess(&self, name: &str, source: &str) -> ReglResult<PreprocessedSource> {
+        let mut expansion = Expansion {
+            files: vec![],
+            lines: vec![],
+            body: vec![],
+            version: None,
+            extensions: vec![],
+            include_stack: vec![],
+            included_once: HashSet::new(),
+            conditional_depth: 0,
+        };
+        try!(self.expand(&mut expansion, name, source));
+
+        let mut output = vec![];
+        let mut lines = vec![];
+        match (&self.version, expansion.version) {
+            (&Some(ref version), _) => {
+                output.push(format!("#version {}", version));
+                lines.push(None);
+            }
+            (&None, Some((directive, location))) => {
+                output.push(directive);
+                lines.push(Some(location));
+            }
+            (&None, None) => {}
+        }
+        for (directive, location) in expansion.extensions {
+            output.push(directive);
+            lines.push(Some(location));
+        }
+        for directive in &self.extensions {
+            output.push(directive.clone());
+            lines.push(None);
+        }
+        for &(ref name, ref value) in &self.defines {
+            output.push(format!("#define {} {}", name, value));
+            lines.push(None);
+        }
+        output.extend(expansion.body);
+        lines.extend(expansion.lines);
+
+        let mut source = output.join("\n");
+        source.push('\n');
+        Ok(PreprocessedSource {
+            source: source,
+            line_map: LineMap {
+                files: expansion.files,
+                lines: lines,
+            },
+        })
+    }
+
+    fn expand(&self, expansion: &mut Expansion, name: &str, source: &str) -> ReglResult<()> {
+        if expansion.included_once.contains(name) {
+            return Ok(());
+        }
+        if expansion.include_stack.iter().any(|n| n == name) {
+            return Err(ReglError::RecursiveInclude(name.to_string()));
+        }
+        let file = expansion.files.len();
+        expansion.files.push(name.to_string());
+        expansion.include_stack.push(name.to_string());
+
+        for (i, line) in source.lines().enumerate() {
+            let location = (file, i as u32 + 1);
+            let directive = match directive(line) {
+                Some(directive) => directive,
+                None => {
+                    expansion.body.push(line.to_string());
+                    expansion.lines.push(Some(location));
+                    continue;
+                }
+            };
+            if directive.starts_with("version") {
+                // Included files may declare a version too; the first one wins
+                if expansion.version.is_none() {
+                    expansion.version = Some((line.trim().to_string(), location));
+                }
+            } else if directive.starts_with("extension") && expansion.conditional_depth == 0 {
+                let line = line.trim().to_string();
+                if !expansion.extensions.iter().any(|&(ref e, _)| *e == line) {
+                    expansion.extensions.push((line, location));
+                }
+            } else if directive == "pragma once" {
+                expansion.included_once.insert(name.to_string());
+            } else if directive.starts_with("include") {
+                let included = match include_name(&directive["include".len()..]) {
+                    Some(included) => included,
+                    None => return Err(ReglError::MalformedInclude(line.trim().to_string())),
+                };
+                let included_source = match self.include_provider
+                                                .and_then(|provider| provider.load(included)) {
+                    Some(source) => source,
+                    None => return Err(ReglError::IncludeNotFound(included.to_string())),
+                };
+                try!(self.expand(expansion, included, &included_source));
+            } else {
+                if directive.starts_with("if") {
+                    expansion.conditional_depth += 1;
+                } else if directive.starts_with("endif") {
+                    expansion.conditional_depth = expansion.conditional_depth.saturating_sub(1);
+                }
+                expansion.body.push(line.to_string());
+                expansion.lines.push(Some(location));
+            }
+        }
+
+        expansion.include_stack.pop();
+        Ok(())
+    }
+}
+
+impl PreprocessedSource {
+    /// The source to be passed to the GL.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn line_map(&self) -> &LineMap {
+        &self.line_map
+    }
+}
+
+impl LineMap {
+    /// The file name and line number that the 1-based `line` of the preprocessed source came
+    /// from, or None if the line was generated.
+    pub fn original_location(&self, line: u32) -> Option<(&str, u32)> {
+        if line == 0 {
+            return None;
+        }
+        match self.lines.get(line as usize - 1) {
+            Some(&Some((file, line))) => Some((&self.files[file], line)),
+            _ => None,
+        }
+    }
+
+    /// Names of all the files that contributed to the source, starting with the main file.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Rewrites the line references of a shader info log, like `0:12` or `0(12)`, to refer to
+    /// the original files.
+    pub fn remap_log(&self, log: &str) -> String {
+        let remapped: Vec<String> = log.lines().map(|line| self.remap_log_line(line)).collect();
+        remapped.join("\n")
+    }
+
+    fn remap_log_line(&self, line: &str) -> String {
+        let reference = match find_line_reference(line) {
+            Some(reference) => reference,
+            None => return line.to_string(),
+        };
+        match self.original_location(reference.line) {
+            Some((file, original_line)) => {
+                let location = if reference.parenthesized {
+                    format!("{}({})", file, original_line)
+                } else {
+                    format!("{}:{}", file, original_line)
+                };
+                format!("{}{}{}", &line[..reference.start], location, &line[reference.end..])
+            }
+            None => line.to_string(),
+        }
+    }
+}
+
+/// Position of a `<source>:<line>` or `<source>(<line>)` reference within a line of an info log.
+#[derive(Debug,Clone,Copy)]
+pub struct LineReference {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub parenthesized: bool,
+}
+
+/// Finds the first line reference in a line of an info log. Mesa writes `0:12(5): error`,
+/// NVIDIA `0(12) : error` and AMD and Intel `ERROR: 0:12: `.
+pub fn find_line_reference(text: &str) -> Option<LineReference> {
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    while start < bytes.len() {
+        let preceded_by_word = start > 0 && is_word_byte(bytes[start - 1]);
+        let source_end = digits_end(bytes, start);
+        if preceded_by_word || source_end == start || source_end >= bytes.len() {
+            start = if source_end > start { source_end } else { start + 1 };
+            continue;
+        }
+        let parenthesized = bytes[source_end] == b'(';
+        if bytes[source_end] == b':' || parenthesized {
+            let line_start = source_end + 1;
+            let line_end = digits_end(bytes, line_start);
+            let closed = !parenthesized || (line_end < bytes.len() && bytes[line_end] == b')');
+            if line_end > line_start && closed {
+                if let Ok(line) = text[line_start..line_end].parse() {
+                    return Some(LineReference {
+                        start: start,
+                        end: if parenthesized { line_end + 1 } else { line_end },
+                        line: line,
+                        parenthesized: parenthesized,
+                    });
+                }
+            }
+        }
+        start = source_end;
+    }
+    None
+}
+
+fn digits_end(bytes: &[u8], start: usize) -> usize {
+    let mut end = start;
+    while end < bytes.len() && (bytes[end] as char).is_digit(10) {
+        end += 1;
+    }
+    end
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    (byte as char).is_alphanumeric() || byte == b'_'
+}
+
+/// The directive of a preprocessor line, without the `#`.
+fn directive(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        Some(line[1..].trim_start())
+    } else {
+        None
+    }
+}
+
+/// The file name of an include directive: `"name"` or `<name>`.
+fn include_name(rest: &str) -> Option<&str> {
+    let rest = rest.trim();
+    let close = match rest.chars().next() {
+        Some('"') => '"',
+        Some('<') => '>',
+        _ => return None,
+    };
+    if rest.len() >= 2 && rest.ends_with(close) {
+        Some(&rest[1..rest.len() - 1])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ReglError;
+    use super::{ShaderPreprocessor, VirtualIncludeProvider, find_line_reference};
+
+    fn provider(files: &[(&str, &str)]) -> VirtualIncludeProvider {
+        let mut provider = VirtualIncludeProvider::new();
+        for &(name, source) in files {
+            provider.add(name, source);
+        }
+        provider
+    }
+
+    #[test]
+    fn expands_nested_includes() {
+        let provider = provider(&[("a.glsl", "#include \"b.glsl\"\nfloat a;"),
+                                  ("b.glsl", "float b;")]);
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .preprocess("main",
+                                           "#version 330\n#include <a.glsl>\nvoid main() {}")
+                               .unwrap();
+        assert_eq!(preprocessed.source(), "#version 330\nfloat b;\nfloat a;\nvoid main() {}\n");
+        assert_eq!(preprocessed.line_map().files(), &["main", "a.glsl", "b.glsl"]);
+    }
+
+    #[test]
+    fn includes_pragma_once_files_once() {
+        let provider = provider(&[("a.glsl", "#pragma once\nfloat a;")]);
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .preprocess("main", "#include \"a.glsl\"\n#include \"a.glsl\"")
+                               .unwrap();
+        assert_eq!(preprocessed.source(), "float a;\n");
+    }
+
+    #[test]
+    fn rejects_recursive_includes() {
+        let provider = provider(&[("a.glsl", "#include \"b.glsl\""),
+                                  ("b.glsl", "#include \"a.glsl\"")]);
+        let result = ShaderPreprocessor::new()
+                         .include_provider(&provider)
+                         .preprocess("main", "#include \"a.glsl\"");
+        match result {
+            Err(ReglError::RecursiveInclude(name)) => assert_eq!(name, "a.glsl"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_malformed_includes() {
+        let provider = provider(&[]);
+        let preprocessor = ShaderPreprocessor::new().include_provider(&provider);
+        match preprocessor.preprocess("main", "#include \"missing.glsl\"") {
+            Err(ReglError::IncludeNotFound(name)) => assert_eq!(name, "missing.glsl"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match preprocessor.preprocess("main", "#include missing.glsl") {
+            Err(ReglError::MalformedInclude(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_lines_to_original_files() {
+        let provider = provider(&[("a.glsl", "float a;\nfloat b;")]);
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .define("N", "4")
+                               .preprocess("main",
+                                           "#version 330\n#include \"a.glsl\"\nvoid main() {}")
+                               .unwrap();
+        let line_map = preprocessed.line_map();
+        assert_eq!(line_map.original_location(1), Some(("main", 1)));
+        // The define is generated
+        assert_eq!(line_map.original_location(2), None);
+        assert_eq!(line_map.original_location(3), Some(("a.glsl", 1)));
+        assert_eq!(line_map.original_location(4), Some(("a.glsl", 2)));
+        assert_eq!(line_map.original_location(5), Some(("main", 3)));
+        assert_eq!(line_map.original_location(0), None);
+        assert_eq!(line_map.original_location(6), None);
+    }
+
+    #[test]
+    fn remaps_log_lines() {
+        let provider = provider(&[("a.glsl", "float a;\nfloat b;")]);
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .preprocess("main", "#include \"a.glsl\"\nvoid main() {}")
+                               .unwrap();
+        let log = "0:2(5): error: oops\n0(3) : warning: hmm\nERROR: 0:1: bad\nno reference";
+        assert_eq!(preprocessed.line_map().remap_log(log),
+                   "a.glsl:2(5): error: oops\nmain(2) : warning: hmm\nERROR: a.glsl:1: bad\n\
+                    no reference");
+    }
+
+    #[test]
+    fn finds_line_references() {
+        let reference = find_line_reference("ERROR: 0:12: bad").unwrap();
+        assert_eq!((reference.start, reference.end, reference.line), (7, 11, 12));
+        assert!(!reference.parenthesized);
+        let reference = find_line_reference("0(7) : error").unwrap();
+        assert_eq!((reference.start, reference.end, reference.line), (0, 4, 7));
+        assert!(reference.parenthesized);
+        assert!(find_line_reference("vec4:1 no").is_none());
+        assert!(find_line_reference("nothing here").is_none());
+    }
+
+    #[test]
+    fn hoists_top_level_extensions() {
+        let provider = provider(&[("a.glsl", "#extension GL_foo : require\nfloat a;")]);
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .define("N", "1")
+                               .preprocess("main",
+                                           "#version 450\n#include \"a.glsl\"\n\
+                                            #extension GL_foo : require\nvoid main() {}")
+                               .unwrap();
+        assert_eq!(preprocessed.source(),
+                   "#version 450\n#extension GL_foo : require\n#define N 1\nfloat a;\n\
+                    void main() {}\n");
+    }
+
+    #[test]
+    fn leaves_conditional_extensions_in_place() {
+        let provider = provider(&[("a.glsl", "#extension GL_bar : enable\nfloat a;")]);
+        let source = "#version 450\n#ifdef USE_FOO\n#extension GL_foo : require\n#endif\n\
+                      #if 1\n#include \"a.glsl\"\n#endif\n#extension GL_baz : enable\n\
+                      void main() {}";
+        let preprocessed = ShaderPreprocessor::new()
+                               .include_provider(&provider)
+                               .preprocess("main", source)
+                               .unwrap();
+        assert_eq!(preprocessed.source(),
+                   "#version 450\n#extension GL_baz : enable\n#ifdef USE_FOO\n\
+                    #extension GL_foo : require\n#endif\n#if 1\n#extension GL_bar : enable\n\
+                    float a;\n#endif\nvoid main() {}\n");
+    }
+}
@@ -0,0 +1,272 @@
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ffi::CStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use gl::types::{GLenum, GLint, GLsizei, GLvoid};
+use id::GlId;
+use shader::ShaderType;
+
+const MAGIC: &'static [u8; 8] = b"REGLPB02";
+const EXTENSION: &'static str = "glbin";
+
+/// Stores linked program binaries on disk (glGetProgramBinary), so that later runs can skip
+/// compiling and linking. Set it with `Context::set_program_cache`.
+///
+/// Binaries are keyed by the shader sources and stages, the bound attribute and output
+/// locations, the vendor, renderer and version of the driver and the binary formats it
+/// supports. The cache is best effort: on a miss, an I/O error or a binary rejected by the
+/// driver the program is compiled and linked as usual.
+///
+/// Binaries are stored in files named after a hash of the key, along with the key itself,
+/// which is compared when loading. A hash collision is therefore just a miss. The hash is
+/// computed with the default hasher of the standard library, which may change between Rust
+/// releases, so updating the compiler may invalidate the cache.
+#[derive(Debug,Clone)]
+pub struct ProgramCache {
+    directory: PathBuf,
+    max_size: Option<u64>,
+}
+
+#[derive(Debug,Clone)]
+pub struct CacheKey {
+    hash: u64,
+    /// Everything the key is made of, serialized.
+    material: Vec<u8>,
+}
+
+impl ProgramCache {
+    /// The directory is created when the first binary is stored.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> ProgramCache {
+        ProgramCache {
+            directory: directory.into(),
+            max_size: None,
+        }
+    }
+
+    /// Limits the total size of the cached binaries, in bytes. The least recently used
+    /// binaries are removed when a new one would exceed the limit.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.directory.join(format!("{:016x}.{}", key.hash, EXTENSION))
+    }
+}
+
+/// None if the driver supports no binary formats, in which case caching is pointless.
+pub fn cache_key(shaders: &[(ShaderType, &str)],
+                 attribute_locations: &[(String, u32)],
+                 output_locations: &[(String, u32)],
+                 separable: bool)
+                 -> Option<CacheKey> {
+    let formats = binary_formats();
+    if formats.is_empty() {
+        return None;
+    }
+    let mut material = vec![];
+    put_u32(&mut material, shaders.len() as u32);
+    for &(shader_type, source) in shaders {
+        put_str(&mut material, &format!("{:?}", shader_type));
+        put_str(&mut material, source);
+    }
+    for locations in &[attribute_locations, output_locations] {
+        put_u32(&mut material, locations.len() as u32);
+        for &(ref name, location) in locations.iter() {
+            put_str(&mut material, name);
+            put_u32(&mut material, location);
+        }
+    }
+    material.push(separable as u8);
+    for &name in &[::gl::VENDOR, ::gl::RENDERER, ::gl::VERSION] {
+        put_str(&mut material, &gl_string(name));
+    }
+    put_u32(&mut material, formats.len() as u32);
+    for &format in &formats {
+        put_u32(&mut material, format as u32);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    material.hash(&mut hasher);
+    Some(CacheKey {
+        hash: hasher.finish(),
+        material: material,
+    })
+}
+
+fn put_u32(material: &mut Vec<u8>, value: u32) {
+    material.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Strings are prefixed with their length, so that no two sequences of them serialize the
+/// same.
+fn put_str(material: &mut Vec<u8>, value: &str) {
+    put_u32(material, value.len() as u32);
+    material.extend_from_slice(value.as_bytes());
+}
+
+/// Creates a program from the cached binary. A binary the driver rejects is removed from the
+/// cache.
+pub fn load_program(cache: &ProgramCache, key: &CacheKey, separable: bool) -> Option<GlId> {
+    let path = cache.path(key);
+    let (format, binary) = match read_binary(&path, key) {
+        Ok(contents) => contents,
+        Err(_) => return None,
+    };
+
+    let gl_id = glcall!(CreateProgram());
+    if separable {
+        super::set_separable(gl_id);
+    }
+    glcall!(ProgramBinary(gl_id,
+                          format,
+                          binary.as_ptr() as *const GLvoid,
+                          binary.len() as GLsizei));
+    let mut linked = 0;
+    glcall!(GetProgramiv(gl_id, ::gl::LINK_STATUS, &mut linked));
+    if linked == 0 {
+        glcall!(DeleteProgram(gl_id));
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+
+    // Mark the binary as recently used for the size limit
+    let _ = File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now()));
+    Some(gl_id)
+}
+
+/// Must be called before linking, for the binary of the program to be retrievable.
+pub fn prepare_program(gl_id: GlId) {
+    glcall!(ProgramParameteri(gl_id, ::gl::PROGRAM_BINARY_RETRIEVABLE_HINT, ::gl::TRUE as GLint));
+}
+
+/// Stores the binary of a linked program. Failures are ignored, the program just won't be
+/// cached.
+pub fn store_program(cache: &ProgramCache, key: &CacheKey, gl_id: GlId) {
+    let mut len = 0;
+    glcall!(GetProgramiv(gl_id, ::gl::PROGRAM_BINARY_LENGTH, &mut len));
+    if len <= 0 {
+        return;
+    }
+    let mut binary = vec![0u8; len as usize];
+    let mut written = 0;
+    let mut format = 0;
+    glcall!(GetProgramBinary(gl_id,
+                             len,
+                             &mut written,
+                             &mut format,
+                             binary.as_mut_ptr() as *mut GLvoid));
+    binary.truncate(written as usize);
+    if binary.is_empty() {
+        return;
+    }
+
+    if let Some(max_size) = cache.max_size {
+        if binary.len() as u64 > max_size {
+            return;
+        }
+        let _ = evict(cache, max_size - binary.len() as u64);
+    }
+    let _ = write_binary(cache, &cache.path(key), key, format, &binary);
+}
+
+/// The file starts with the magic, the length of the key material and the material, followed
+/// by the binary format and the binary.
+fn read_binary(path: &Path, key: &CacheKey) -> io::Result<(GLenum, Vec<u8>)> {
+    let mut contents = vec![];
+    try!(try!(File::open(path)).read_to_end(&mut contents));
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    if contents.len() < MAGIC.len() + 4 || &contents[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a program binary"));
+    }
+    let material_start = MAGIC.len() + 4;
+    let material_len = read_u32(&contents[MAGIC.len()..]) as usize;
+    let format_start = material_start + material_len;
+    if contents.len() < format_start + 4 {
+        return Err(invalid("truncated program binary"));
+    }
+    if &contents[material_start..format_start] != &key.material[..] {
+        return Err(invalid("program binary of another key"));
+    }
+    let format = read_u32(&contents[format_start..]);
+    let binary = contents.split_off(format_start + 4);
+    Ok((format, binary))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut value = [0u8; 4];
+    value.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(value)
+}
+
+fn write_binary(cache: &ProgramCache,
+                path: &Path,
+                key: &CacheKey,
+                format: GLenum,
+                binary: &[u8])
+                -> io::Result<()> {
+    try!(fs::create_dir_all(&cache.directory));
+    // Write to a temporary file first, so that a crash can't leave a truncated binary behind
+    let temporary = path.with_extension("tmp");
+    {
+        let mut file = try!(File::create(&temporary));
+        try!(file.write_all(MAGIC));
+        try!(file.write_all(&(key.material.len() as u32).to_le_bytes()));
+        try!(file.write_all(&key.material));
+        try!(file.write_all(&format.to_le_bytes()));
+        try!(file.write_all(binary));
+    }
+    fs::rename(&temporary, path)
+}
+
+/// Removes the least recently used binaries until at most `target_size` bytes remain.
+fn evict(cache: &ProgramCache, target_size: u64) -> io::Result<()> {
+    let mut entries = vec![];
+    for entry in try!(fs::read_dir(&cache.directory)) {
+        let entry = try!(entry);
+        let path = entry.path();
+        if path.extension().map_or(false, |e| e == EXTENSION) {
+            let metadata = try!(entry.metadata());
+            entries.push((try!(metadata.modified()), metadata.len(), path));
+        }
+    }
+    let mut total: u64 = entries.iter().map(|&(_, len, _)| len).sum();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, len, path) in entries {
+        if total <= target_size {
+            break;
+        }
+        try!(fs::remove_file(&path));
+        total -= len;
+    }
+    Ok(())
+}
+
+fn binary_formats() -> Vec<GLint> {
+    let mut count = 0;
+    glcall!(GetIntegerv(::gl::NUM_PROGRAM_BINARY_FORMATS, &mut count));
+    if count <= 0 {
+        return vec![];
+    }
+    let mut formats = vec![0; count as usize];
+    glcall!(GetIntegerv(::gl::PROGRAM_BINARY_FORMATS, formats.as_mut_ptr()));
+    formats
+}
+
+fn gl_string(name: GLenum) -> String {
+    let value = glcall!(GetString(name));
+    if value.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(value as *const c_char) }.to_string_lossy().into_owned()
+}
@@ -1,65 +1,309 @@
 
 use std::rc::Rc;
+use std::cell::{Cell, RefCell, RefMut};
 use std::fmt::Debug;
-use gl::types::{GLenum, GLint};
+use std::ffi::CString;
+use gl::types::{GLenum, GLint, GLbitfield};
 use id::{Id, GenerateId, GlId};
 use ReglResult;
 use ReglError;
-use tracker::BindIf;
-use resource::ResourceCreationSupport;
-use shader::{Shader, InternalShader};
+use tracker::{BindIf, UnbindIf};
+use shader::{self, Shader, ShaderType, ShaderSource, ShaderCreationSupport, InternalShader};
+use diagnostic::InfoLog;
+use self::shadow::{UniformCache, Component};
 
 pub use self::attribute::{AttributeInfo, ShaderAttribute, ShaderAttributeType};
-pub use self::uniform::{UniformInfo, Uniform, InterfaceBlock, BlockUniform, UniformType};
+pub use self::uniform::{UniformInfo, Uniform, InterfaceBlock, BlockUniform, UniformType,
+                        AtomicCounterBuffer};
+pub use self::value::{UniformValue, UniformElement};
+pub use self::shadow::UniformCacheStats;
+pub use self::storage::{StorageBlockInfo, StorageBlock, BufferVariable};
+pub use self::cache::ProgramCache;
+pub use self::reload::{ReloadableProgram, ReloadStatus};
+pub use self::pending::PendingProgram;
+pub use self::pipeline::ProgramPipeline;
 
 mod attribute;
 mod uniform;
+mod value;
+mod shadow;
+mod storage;
+mod cache;
+mod reload;
+mod pending;
+mod pipeline;
 
-pub trait ProgramCreationSupport : ResourceCreationSupport {
+pub trait ProgramCreationSupport : ShaderCreationSupport {
     fn validate_after_linking(&self) -> bool;
+    fn program_cache(&self) -> Option<&ProgramCache>;
+    /// Whether the driver compiles in the background and can report when it's done
+    /// (KHR_parallel_shader_compile).
+    fn parallel_shader_compile(&self) -> bool;
 }
 
-pub trait ProgramSupport : BindIf<Program> + Debug {}
+pub trait ProgramSupport : BindIf<Program> + UnbindIf<Program> + BindIf<ProgramPipeline>
+    + Debug {}
 
 pub trait ProgramInternal {
     fn bind(&self);
 }
 
+/// The shader stages of a draw call: a `Program`, or a `ProgramPipeline` of separable
+/// programs.
+pub trait DrawProgram : ProgramInternal {
+    /// The stages used for drawing. Fails if they can't draw at all, e.g. for a pipeline
+    /// without a vertex stage.
+    fn draw_stages(&self) -> ReglResult<ProgramStages>;
+}
+
 #[derive(Debug)]
 pub struct Program {
     shared_context: Rc<ProgramSupport>,
     uid: Id,
     gl_id: GlId,
+    layout: ProgramLayout,
+    /// Shadow copy of the uniform values, created when the first uniform is set.
+    uniform_cache: RefCell<Option<UniformCache>>,
+    uniform_cache_stats: Cell<UniformCacheStats>,
+}
+
+/// What is known of a program before it has been linked.
+#[derive(Debug,Clone,Copy)]
+struct ProgramLayout {
+    stages: ProgramStages,
+    /// The stages as glUseProgramStages bits.
+    stage_bits: GLbitfield,
+    separable: bool,
+}
+
+/// The shader stages linked into a program.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ProgramStages {
+    /// Vertex and fragment processing, optionally with tessellation and geometry stages.
+    Graphics {
+        tessellation: bool,
+        geometry: bool,
+    },
+    /// A compute shader with the local work group size it declares.
+    Compute {
+        work_group_size: [u32; 3],
+    },
+}
+
+/// Builds a program with explicitly bound attribute and fragment output locations.
+///
+/// The locations are bound before linking, so that they override whatever the linker would
+/// choose. That way several programs can agree on the locations and share vertex arrays.
+#[derive(Debug)]
+pub struct ProgramBuilder<'a> {
+    shaders: ProgramShaders<'a>,
+    attribute_locations: Vec<(String, u32)>,
+    output_locations: Vec<(String, u32)>,
+    detach_shaders: bool,
+    separable: bool,
+}
+
+#[derive(Debug,Clone,Copy)]
+enum ProgramShaders<'a> {
+    Compiled(&'a [Shader]),
+    /// Compiled only if the program isn't found in the program cache.
+    Source(&'a [ShaderSource<'a>]),
+}
+
+impl<'a> ProgramBuilder<'a> {
+    pub fn new(shaders: &'a [Shader]) -> ProgramBuilder<'a> {
+        ProgramBuilder::with_shaders(ProgramShaders::Compiled(shaders))
+    }
+
+    /// Builds the program from source. With a program cache, the shaders are compiled only
+    /// if the program isn't found in the cache.
+    pub fn from_sources(sources: &'a [ShaderSource<'a>]) -> ProgramBuilder<'a> {
+        ProgramBuilder::with_shaders(ProgramShaders::Source(sources))
+    }
+
+    fn with_shaders(shaders: ProgramShaders<'a>) -> ProgramBuilder<'a> {
+        ProgramBuilder {
+            shaders: shaders,
+            attribute_locations: vec![],
+            output_locations: vec![],
+            detach_shaders: false,
+            separable: false,
+        }
+    }
+
+    /// Binds the named vertex attribute to the location (glBindAttribLocation).
+    pub fn attribute_location<T: Into<String>>(mut self, name: T, location: u32) -> Self {
+        self.attribute_locations.push((name.into(), location));
+        self
+    }
+
+    pub fn attribute_locations<T, I>(mut self, locations: I) -> Self
+        where T: Into<String>,
+              I: IntoIterator<Item = (T, u32)>
+    {
+        self.attribute_locations.extend(locations.into_iter().map(|(n, l)| (n.into(), l)));
+        self
+    }
+
+    /// Binds the named fragment shader output to the color number (glBindFragDataLocation).
+    pub fn output_location<T: Into<String>>(mut self, name: T, location: u32) -> Self {
+        self.output_locations.push((name.into(), location));
+        self
+    }
+
+    pub fn output_locations<T, I>(mut self, locations: I) -> Self
+        where T: Into<String>,
+              I: IntoIterator<Item = (T, u32)>
+    {
+        self.output_locations.extend(locations.into_iter().map(|(n, l)| (n.into(), l)));
+        self
+    }
+
+    /// Detach the shaders from the program after it has been linked successfully, so that
+    /// the driver can free them as soon as the `Shader`s are dropped.
+    pub fn detach_shaders(mut self, detach: bool) -> Self {
+        self.detach_shaders = detach;
+        self
+    }
+
+    /// Link a separable program (GL_PROGRAM_SEPARABLE), which may contain any subset of the
+    /// graphics stages, to be combined with other programs in a `ProgramPipeline`.
+    pub fn separable(mut self, separable: bool) -> Self {
+        self.separable = separable;
+        self
+    }
+
+    pub fn build<C: ProgramCreationSupport>(&self, support: &mut C) -> ReglResult<Program> {
+        let validate = support.validate_after_linking();
+        let pending = try!(self.submit(support));
+        pending::complete(pending, validate)
+    }
+
+    /// Submits the shaders for compiling and linking, without waiting for the driver to
+    /// finish. See `PendingProgram`.
+    pub fn build_pending<C: ProgramCreationSupport>(&self,
+                                                    support: &mut C)
+                                                    -> ReglResult<PendingProgram> {
+        self.submit(support)
+    }
+
+    fn submit<C: ProgramCreationSupport>(&self, support: &mut C) -> ReglResult<PendingProgram> {
+        let sources: Vec<(ShaderType, &str)> = match self.shaders {
+            ProgramShaders::Compiled(shaders) => {
+                shaders.iter().map(|s| (s.shader_type(), s.source())).collect()
+            }
+            ProgramShaders::Source(sources) => sources.iter().map(|s| (s.0, s.1)).collect(),
+        };
+        let shader_types: Vec<ShaderType> = sources.iter().map(|&(t, _)| t).collect();
+        let layout = ProgramLayout {
+            stages: try!(program_stages(&shader_types, self.separable)),
+            stage_bits: shader_types.iter().fold(0, |bits, &t| bits | gl_stage_bit(t)),
+            separable: self.separable,
+        };
+        let c_attribute_names = try!(c_names(&self.attribute_locations));
+        let c_output_names = try!(c_names(&self.output_locations));
+
+        let cache_key = match support.program_cache() {
+            Some(_) => {
+                cache::cache_key(&sources,
+                                 &self.attribute_locations,
+                                 &self.output_locations,
+                                 self.separable)
+            }
+            None => None,
+        };
+        let cached = match (support.program_cache(), &cache_key) {
+            (Some(program_cache), &Some(ref key)) => {
+                cache::load_program(program_cache, key, self.separable)
+            }
+            _ => None,
+        };
+        if let Some(gl_id) = cached {
+            return Ok(pending::new_pending_program(support, gl_id, layout, vec![], vec![], None));
+        }
+
+        let mut compiled = vec![];
+        let mut detach = vec![];
+        match self.shaders {
+            ProgramShaders::Compiled(shaders) => {
+                if self.detach_shaders {
+                    detach.extend(shaders.iter().map(|s| s.gl_id()));
+                }
+            }
+            ProgramShaders::Source(sources) => {
+                for source in sources {
+                    compiled.push(try!(shader::compile_unchecked(source)));
+                }
+                // The shaders are dropped along with the PendingProgram
+                detach.extend(compiled.iter().map(|s| s.gl_id()));
+            }
+        }
+
+        let gl_id = glcall!(CreateProgram());
+
+        match self.shaders {
+            ProgramShaders::Compiled(shaders) => {
+                for shader in shaders {
+                    glcall!(AttachShader(gl_id, shader.gl_id()));
+                }
+            }
+            ProgramShaders::Source(_) => {
+                for shader in &compiled {
+                    glcall!(AttachShader(gl_id, shader.gl_id()));
+                }
+            }
+        }
+        for (&(_, location), c_name) in self.attribute_locations.iter().zip(c_attribute_names) {
+            glcall!(BindAttribLocation(gl_id, location, c_name.as_ptr()));
+        }
+        for (&(_, location), c_name) in self.output_locations.iter().zip(c_output_names) {
+            glcall!(BindFragDataLocation(gl_id, location, c_name.as_ptr()));
+        }
+        if self.separable {
+            set_separable(gl_id);
+        }
+        if cache_key.is_some() {
+            cache::prepare_program(gl_id);
+        }
+        glcall!(LinkProgram(gl_id));
+
+        let cache = match (support.program_cache(), cache_key) {
+            (Some(program_cache), Some(key)) => Some((program_cache.clone(), key)),
+            _ => None,
+        };
+        Ok(pending::new_pending_program(support, gl_id, layout, compiled, detach, cache))
+    }
 }
 
 impl Program {
     pub fn new<C: ProgramCreationSupport>(support: &mut C,
                                           shaders: &[Shader])
                                           -> ReglResult<Program> {
-        let gl_id = glcall!(CreateProgram());
+        ProgramBuilder::new(shaders).build(support)
+    }
 
-        for shader in shaders {
-            glcall!(AttachShader(gl_id, shader.gl_id()));
-        }
+    /// Compiles and links the shaders, unless the program is found in the program cache.
+    pub fn from_sources<C: ProgramCreationSupport>(support: &mut C,
+                                                   sources: &[ShaderSource])
+                                                   -> ReglResult<Program> {
+        ProgramBuilder::from_sources(sources).build(support)
+    }
 
-        glcall!(LinkProgram(gl_id));
-        if support.validate_after_linking() && !linked(gl_id) {
-            return Err(ReglError::ProgramLinkingError(info_log(gl_id)));
-        }
+    pub fn stages(&self) -> ProgramStages {
+        self.layout.stages
+    }
 
-        Ok(Program {
-            shared_context: support.get_shared_context(),
-            uid: support.generate_id(),
-            gl_id: gl_id,
-        })
+    /// Whether the program was linked with `ProgramBuilder::separable`.
+    pub fn is_separable(&self) -> bool {
+        self.layout.separable
     }
 
     pub fn validate(&self) -> bool {
         gl_program_value(self.gl_id, ::gl::VALIDATE_STATUS) != 0
     }
 
-    pub fn info_log(&self) -> String {
-        info_log(self.gl_id)
+    pub fn info_log(&self) -> InfoLog {
+        InfoLog::parse(info_log(self.gl_id))
     }
 
     pub fn attribute_info(&self) -> AttributeInfo {
@@ -78,14 +322,70 @@ impl Program {
         uniform::get_uniform_location(self.gl_id, name.as_ref())
     }
 
+    /// Sets the value of the named global uniform, checking that its type matches the GLSL type
+    /// of the uniform and that an array fits in the uniform array. A slice sets the elements
+    /// of an array uniform starting from the named one, e.g. `lights` or `lights[2]`.
+    pub fn set_uniform<N, T>(&self, name: N, value: &T) -> ReglResult<()>
+        where N: AsRef<str>,
+              T: UniformValue + ?Sized
+    {
+        let name = name.as_ref();
+        let (location, uniform_type, remaining) = try!(self.uniform_element(name));
+        if !value::accepts(uniform_type, value.element_type()) {
+            return Err(ReglError::UniformTypeMismatch);
+        }
+        if value.len() > remaining {
+            return Err(ReglError::UniformArraySizeExceeded(name.to_string()));
+        }
+        if value.len() == 0 {
+            return Ok(());
+        }
+        value.set(self, location, uniform_type)
+    }
+
+    /// Location, type and number of array elements from the named one onwards.
+    fn uniform_element(&self, name: &str) -> ReglResult<(i32, UniformType, u32)> {
+        let cache = self.uniform_cache();
+        let (uniform, index) = match uniform::find_global_element(cache.info(), name) {
+            Some(found) => found,
+            None => return Err(ReglError::InactiveUniform(name.to_string())),
+        };
+        let location = if index == 0 {
+            uniform.location
+        } else {
+            try!(uniform::get_uniform_location(self.gl_id, name))
+        };
+        Ok((location, uniform.uniform_type, uniform.size as u32 - index))
+    }
+
+    /// Makes the named uniform block read its data from the uniform buffer binding point
+    /// `binding`. See `Buffer::bind_range`.
+    pub fn uniform_block_binding<T: AsRef<str>>(&self, name: T, binding: u32) -> ReglResult<()> {
+        uniform::set_uniform_block_binding(self.gl_id, name.as_ref(), binding)
+    }
+
+    pub fn storage_block_info(&self) -> StorageBlockInfo {
+        storage::get_storage_block_info(self.gl_id)
+    }
+
+    /// Makes the named shader storage block use the shader storage buffer binding point
+    /// `binding`. See `Buffer::bind_range`.
+    pub fn storage_block_binding<T: AsRef<str>>(&self, name: T, binding: u32) -> ReglResult<()> {
+        storage::set_storage_block_binding(self.gl_id, name.as_ref(), binding)
+    }
+
+    /// Setting a uniform to the value it already has is skipped. The values are tracked per
+    /// program, starting from the values after linking; see `uniform_cache_stats`.
     pub fn uniform_f32(&self,
                        location: i32,
                        uniform_type: UniformType,
                        count: u32,
                        values: &[f32])
                        -> ReglResult<()> {
-        self.bind();
-        uniform::uniform_value_f32(location, uniform_type, count, values)
+        try!(uniform::float_components(uniform_type));
+        self.set_cached(location, uniform_type, count, values, &|| {
+            uniform::uniform_value_f32(location, uniform_type, count, values)
+        })
     }
 
     pub fn uniform_u32(&self,
@@ -94,8 +394,10 @@ impl Program {
                        count: u32,
                        values: &[u32])
                        -> ReglResult<()> {
-        self.bind();
-        uniform::uniform_value_u32(location, uniform_type, count, values)
+        try!(uniform::unsigned_int_components(uniform_type));
+        self.set_cached(location, uniform_type, count, values, &|| {
+            uniform::uniform_value_u32(location, uniform_type, count, values)
+        })
     }
 
     pub fn uniform_i32(&self,
@@ -104,10 +406,13 @@ impl Program {
                        count: u32,
                        values: &[i32])
                        -> ReglResult<()> {
-        self.bind();
-        uniform::uniform_value_i32(location, uniform_type, count, values)
+        try!(uniform::int_components(uniform_type));
+        self.set_cached(location, uniform_type, count, values, &|| {
+            uniform::uniform_value_i32(location, uniform_type, count, values)
+        })
     }
 
+    /// Transposed matrices are always set, as the values are compared as given.
     pub fn uniform_matrix(&self,
                           location: i32,
                           uniform_type: UniformType,
@@ -115,8 +420,64 @@ impl Program {
                           values: &[f32],
                           transpose: bool)
                           -> ReglResult<()> {
+        try!(uniform::matrix_components(uniform_type));
+        if !transpose {
+            return self.set_cached(location, uniform_type, count, values, &|| {
+                uniform::uniform_value_matrix(location, uniform_type, count, values, false)
+            });
+        }
         self.bind();
-        uniform::uniform_value_matrix(location, uniform_type, count, values, transpose)
+        try!(uniform::uniform_value_matrix(location, uniform_type, count, values, true));
+        self.uniform_cache().forget(location, count);
+        self.count_uniform_update(false);
+        Ok(())
+    }
+
+    /// How many updates through the `uniform_*` setters (and `set_uniform`) were passed to the
+    /// GL, and how many were skipped because the uniform already had the value.
+    pub fn uniform_cache_stats(&self) -> UniformCacheStats {
+        self.uniform_cache_stats.get()
+    }
+
+    pub fn reset_uniform_cache_stats(&self) {
+        self.uniform_cache_stats.set(UniformCacheStats::default());
+    }
+
+    fn set_cached<T: Component>(&self,
+                                location: i32,
+                                uniform_type: UniformType,
+                                count: u32,
+                                values: &[T],
+                                set: &Fn() -> ReglResult<()>)
+                                -> ReglResult<()> {
+        let mut cache = self.uniform_cache();
+        if cache.unchanged(location, uniform_type, count, values) {
+            self.count_uniform_update(true);
+            return Ok(());
+        }
+        self.bind();
+        try!(set());
+        cache.record(location, uniform_type, count, values);
+        self.count_uniform_update(false);
+        Ok(())
+    }
+
+    fn uniform_cache(&self) -> RefMut<'_, UniformCache> {
+        let mut cache = self.uniform_cache.borrow_mut();
+        if cache.is_none() {
+            *cache = Some(UniformCache::new(self.gl_id));
+        }
+        RefMut::map(cache, |cache| cache.as_mut().unwrap())
+    }
+
+    fn count_uniform_update(&self, skipped: bool) {
+        let mut stats = self.uniform_cache_stats.get();
+        if skipped {
+            stats.skipped += 1;
+        } else {
+            stats.updates += 1;
+        }
+        self.uniform_cache_stats.set(stats);
     }
 
     fn gl_bind(&self) {
@@ -126,8 +487,79 @@ impl Program {
 
 impl ProgramInternal for Program {
     fn bind(&self) {
-        self.shared_context.bind_if(&self.uid, &|| self.gl_bind());
+        BindIf::<Program>::bind_if(&*self.shared_context, &self.uid, &|| self.gl_bind());
+    }
+}
+
+impl DrawProgram for Program {
+    fn draw_stages(&self) -> ReglResult<ProgramStages> {
+        Ok(self.layout.stages)
+    }
+}
+
+/// Checks that the shaders make up a complete pipeline: either a lone compute stage, or at
+/// least a vertex stage, with the tessellation control stage only alongside an evaluation stage.
+/// Separable programs are only part of a pipeline and may contain any graphics stages.
+fn program_stages(shader_types: &[ShaderType], separable: bool) -> ReglResult<ProgramStages> {
+    let has = |shader_type| shader_types.iter().any(|&t| t == shader_type);
+    if shader_types.is_empty() {
+        return Err(ReglError::InvalidShaderStages("no shaders given".to_string()));
+    }
+    if has(ShaderType::ComputeShader) {
+        if shader_types.iter().any(|&t| t != ShaderType::ComputeShader) {
+            let message = "a compute shader can't be linked with other stages";
+            return Err(ReglError::InvalidShaderStages(message.to_string()));
+        }
+        // The work group size is known only after linking
+        return Ok(ProgramStages::Compute { work_group_size: [0; 3] });
+    }
+    if separable {
+        return Ok(ProgramStages::Graphics {
+            tessellation: has(ShaderType::TessEvaluationShader),
+            geometry: has(ShaderType::GeometryShader),
+        });
+    }
+    if !has(ShaderType::VertexShader) {
+        return Err(ReglError::InvalidShaderStages("missing vertex shader".to_string()));
+    }
+    if has(ShaderType::TessControlShader) && !has(ShaderType::TessEvaluationShader) {
+        let message = "a tessellation control shader requires a tessellation evaluation shader";
+        return Err(ReglError::InvalidShaderStages(message.to_string()));
+    }
+    Ok(ProgramStages::Graphics {
+        tessellation: has(ShaderType::TessEvaluationShader),
+        geometry: has(ShaderType::GeometryShader),
+    })
+}
+
+fn gl_stage_bit(shader_type: ShaderType) -> GLbitfield {
+    match shader_type {
+        ShaderType::VertexShader => ::gl::VERTEX_SHADER_BIT,
+        ShaderType::TessControlShader => ::gl::TESS_CONTROL_SHADER_BIT,
+        ShaderType::TessEvaluationShader => ::gl::TESS_EVALUATION_SHADER_BIT,
+        ShaderType::GeometryShader => ::gl::GEOMETRY_SHADER_BIT,
+        ShaderType::FragmentShader => ::gl::FRAGMENT_SHADER_BIT,
+        ShaderType::ComputeShader => ::gl::COMPUTE_SHADER_BIT,
+    }
+}
+
+/// Must be called before linking or loading a binary.
+fn set_separable(gl_id: GlId) {
+    glcall!(ProgramParameteri(gl_id, ::gl::PROGRAM_SEPARABLE, ::gl::TRUE as GLint));
+}
+
+fn get_work_group_size(gl_id: GlId) -> [u32; 3] {
+    let mut size = [0 as GLint; 3];
+    glcall!(GetProgramiv(gl_id, ::gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr()));
+    [size[0] as u32, size[1] as u32, size[2] as u32]
+}
+
+fn c_names(locations: &[(String, u32)]) -> ReglResult<Vec<CString>> {
+    let mut c_names = Vec::with_capacity(locations.len());
+    for &(ref name, _) in locations {
+        c_names.push(try!(CString::new(&name[..])));
     }
+    Ok(c_names)
 }
 
 fn linked(gl_id: GlId) -> bool {
@@ -0,0 +1,123 @@
+
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use gl::types::GLenum;
+use id::{Id, GlId};
+use ReglResult;
+use ReglError;
+use shader::{self, Shader};
+use diagnostic::InfoLog;
+use super::{Program, ProgramStages, ProgramLayout, ProgramSupport, ProgramCreationSupport,
+            ProgramCache};
+use super::cache::{self, CacheKey};
+
+/// COMPLETION_STATUS_KHR of KHR_parallel_shader_compile, missing from the bindings.
+const COMPLETION_STATUS: GLenum = 0x91B1;
+
+/// A program whose shaders have been submitted for compiling and linking, but whose status
+/// hasn't been queried yet. Querying it waits for the driver to finish, so a loading screen
+/// can keep rendering while `is_ready` returns false, and call `finish` afterwards.
+///
+/// Create one with `ProgramBuilder::build_pending`. Dropping it deletes the program.
+#[derive(Debug)]
+pub struct PendingProgram {
+    shared_context: Rc<ProgramSupport>,
+    /// None once the program has been finished.
+    uid: Option<Id>,
+    gl_id: GlId,
+    layout: ProgramLayout,
+    /// Shaders compiled from source for this program.
+    shaders: Vec<Shader>,
+    /// Shaders to detach once the program has been linked.
+    detach: Vec<GlId>,
+    cache: Option<(ProgramCache, CacheKey)>,
+    completion_query: bool,
+    /// Whether the compile status of the shaders is checked even if linking isn't.
+    validate_compilation: bool,
+}
+
+impl PendingProgram {
+    /// Whether compiling and linking has finished, so that `finish` won't block. Without
+    /// KHR_parallel_shader_compile the driver can't tell, and this is always true.
+    pub fn is_ready(&self) -> bool {
+        if !self.completion_query {
+            return true;
+        }
+        let mut value = 0;
+        glcall!(GetProgramiv(self.gl_id, COMPLETION_STATUS, &mut value));
+        value != 0
+    }
+
+    /// Waits for compiling and linking to finish. Fails with `ShaderCompilationError` if a
+    /// shader compiled from source failed, and with `ProgramLinkingError` otherwise.
+    pub fn finish(self) -> ReglResult<Program> {
+        complete(self, true)
+    }
+}
+
+impl Drop for PendingProgram {
+    fn drop(&mut self) {
+        if self.uid.is_some() {
+            glcall!(DeleteProgram(self.gl_id));
+        }
+    }
+}
+
+pub fn new_pending_program<C: ProgramCreationSupport>(support: &mut C,
+                                                      gl_id: GlId,
+                                                      layout: ProgramLayout,
+                                                      shaders: Vec<Shader>,
+                                                      detach: Vec<GlId>,
+                                                      cache: Option<(ProgramCache, CacheKey)>)
+                                                      -> PendingProgram {
+    PendingProgram {
+        shared_context: support.get_shared_context(),
+        uid: Some(support.generate_id()),
+        gl_id: gl_id,
+        layout: layout,
+        shaders: shaders,
+        detach: detach,
+        cache: cache,
+        completion_query: support.parallel_shader_compile(),
+        validate_compilation: support.validate_after_compilation(),
+    }
+}
+
+/// Turns the pending program into a program. The compile status of the shaders is queried if
+/// the context validates after compilation, and the link status only if `validate` is set, or
+/// if the program is to be cached.
+pub fn complete(mut pending: PendingProgram, validate: bool) -> ReglResult<Program> {
+    let gl_id = pending.gl_id;
+    if pending.validate_compilation {
+        for shader in &pending.shaders {
+            try!(shader::check_compiled(shader));
+        }
+    }
+    if (validate || pending.cache.is_some()) && !super::linked(gl_id) {
+        // Report the first failed shader, as its log says more than the linker's
+        for shader in &pending.shaders {
+            try!(shader::check_compiled(shader));
+        }
+        return Err(ReglError::ProgramLinkingError(InfoLog::parse(super::info_log(gl_id))));
+    }
+
+    if let Some((ref program_cache, ref key)) = pending.cache {
+        cache::store_program(program_cache, key, gl_id);
+    }
+    for &shader in &pending.detach {
+        glcall!(DetachShader(gl_id, shader));
+    }
+    let mut layout = pending.layout;
+    if let ProgramStages::Compute { ref mut work_group_size } = layout.stages {
+        *work_group_size = super::get_work_group_size(gl_id);
+    }
+
+    Ok(Program {
+        shared_context: pending.shared_context.clone(),
+        uid: pending.uid.take().unwrap(),
+        gl_id: gl_id,
+        layout: layout,
+        uniform_cache: RefCell::new(None),
+        uniform_cache_stats: Cell::new(Default::default()),
+    })
+}
@@ -0,0 +1,117 @@
+
+use std::rc::Rc;
+use gl::types::{GLbitfield, GLenum, GLint};
+use id::{Id, GlId};
+use ReglResult;
+use ReglError;
+use resource::ResourceCreationSupport;
+use tracker::{BindIf, UnbindIf};
+use shader::ShaderType;
+use diagnostic::InfoLog;
+use super::{Program, ProgramStages, ProgramSupport, ProgramInternal, DrawProgram};
+
+/// Combines stages of separable programs (glUseProgramStages), so that e.g. one vertex program
+/// can be used with many fragment programs without linking every combination. See
+/// `ProgramBuilder::separable`.
+///
+/// A program made current with glUseProgram overrides the bound pipeline, so drawing with a
+/// pipeline also makes sure that no program is current.
+#[derive(Debug)]
+pub struct ProgramPipeline {
+    shared_context: Rc<ProgramSupport>,
+    uid: Id,
+    gl_id: GlId,
+    /// Stages that have a program, as glUseProgramStages bits.
+    stage_bits: GLbitfield,
+}
+
+impl ProgramPipeline {
+    pub fn new<C: ResourceCreationSupport>(support: &mut C) -> ProgramPipeline {
+        let mut gl_id = 0;
+        glcall!(GenProgramPipelines(1, &mut gl_id));
+        ProgramPipeline {
+            shared_context: support.get_shared_context(),
+            uid: support.generate_id(),
+            gl_id: gl_id,
+            stage_bits: 0,
+        }
+    }
+
+    /// Uses `program` for all the stages it contains, replacing the programs previously used
+    /// for those stages. The program must be separable, and can't be a compute program, as
+    /// pipelines are only used for drawing.
+    pub fn use_program_stages(&mut self, program: &Program) -> ReglResult<()> {
+        if !program.layout.separable {
+            return Err(ReglError::ProgramNotSeparable);
+        }
+        if let ProgramStages::Compute { .. } = program.layout.stages {
+            return Err(ReglError::ComputeProgramInPipeline);
+        }
+        let stage_bits = program.layout.stage_bits;
+        glcall!(UseProgramStages(self.gl_id, stage_bits, program.gl_id));
+        self.stage_bits |= stage_bits;
+        Ok(())
+    }
+
+    /// Removes the programs of the given stages from the pipeline.
+    pub fn clear_stages(&mut self, stages: &[ShaderType]) {
+        let stage_bits = stages.iter().fold(0, |bits, &t| bits | super::gl_stage_bit(t));
+        glcall!(UseProgramStages(self.gl_id, stage_bits, 0));
+        self.stage_bits &= !stage_bits;
+    }
+
+    /// Checks whether the programs of the pipeline can be used together with the current GL
+    /// state (glValidateProgramPipeline). See `info_log` for the reason if not.
+    pub fn validate(&self) -> bool {
+        glcall!(ValidateProgramPipeline(self.gl_id));
+        self.pipeline_value(::gl::VALIDATE_STATUS) != 0
+    }
+
+    pub fn info_log(&self) -> InfoLog {
+        let len = self.pipeline_value(::gl::INFO_LOG_LENGTH);
+        if len <= 0 {
+            return InfoLog::parse(String::new());
+        }
+        let mut actual_len = 0;
+        let mut log = vec![0u8; len as usize];
+        glcall!(GetProgramPipelineInfoLog(self.gl_id,
+                                          len,
+                                          &mut actual_len,
+                                          log.as_mut_ptr() as *mut i8));
+        log.truncate(actual_len as usize);
+        InfoLog::parse(String::from_utf8_lossy(&log[..]).into_owned())
+    }
+
+    fn pipeline_value(&self, key: GLenum) -> GLint {
+        let mut value = 0;
+        glcall!(GetProgramPipelineiv(self.gl_id, key, &mut value));
+        value
+    }
+}
+
+impl ProgramInternal for ProgramPipeline {
+    fn bind(&self) {
+        UnbindIf::<Program>::unbind_if(&*self.shared_context, &|| glcall!(UseProgram(0)));
+        BindIf::<ProgramPipeline>::bind_if(&*self.shared_context,
+                                           &self.uid,
+                                           &|| glcall!(BindProgramPipeline(self.gl_id)));
+    }
+}
+
+impl DrawProgram for ProgramPipeline {
+    fn draw_stages(&self) -> ReglResult<ProgramStages> {
+        if self.stage_bits & ::gl::VERTEX_SHADER_BIT == 0 {
+            return Err(ReglError::MissingVertexStage);
+        }
+        Ok(ProgramStages::Graphics {
+            tessellation: self.stage_bits & ::gl::TESS_EVALUATION_SHADER_BIT != 0,
+            geometry: self.stage_bits & ::gl::GEOMETRY_SHADER_BIT != 0,
+        })
+    }
+}
+
+impl Drop for ProgramPipeline {
+    fn drop(&mut self) {
+        glcall!(DeleteProgramPipelines(1, &self.gl_id));
+    }
+}
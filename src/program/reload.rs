@@ -0,0 +1,263 @@
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use ReglResult;
+use ReglError;
+use shader::{self, Shader, ShaderType};
+use preprocessor::{ShaderPreprocessor, IncludeProvider, PreprocessedSource};
+use super::{Program, ProgramBuilder, ProgramCreationSupport};
+
+/// A program built from shader files, that is rebuilt when the files change.
+///
+/// The files are preprocessed with a `ShaderPreprocessor`, with all includes, nested ones too,
+/// resolved relative to the directory of the main shader file. The included files are watched
+/// too, including ones that couldn't be found, so that creating them triggers a reload.
+/// Changes are detected by polling the modification times in `poll`, e.g. once per frame.
+///
+/// When rebuilding fails, the previous program is kept in use and the error, with
+/// diagnostics referring to the original files, is available from `last_error`. The compile
+/// and link status are checked even if the context doesn't validate them otherwise.
+#[derive(Debug)]
+pub struct ReloadableProgram {
+    shaders: Vec<(ShaderType, PathBuf)>,
+    program: Program,
+    watched: Vec<WatchedFile>,
+    generation: u32,
+    last_error: Option<ReglError>,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ReloadStatus {
+    Unchanged,
+    /// The program was replaced. Uniform values must be set again.
+    Reloaded,
+    /// Rebuilding failed and the previous program is still in use; see `last_error`.
+    Failed,
+}
+
+#[derive(Debug)]
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ReloadableProgram {
+    /// Builds the program from the shader files. Unlike later reloads, this fails if the
+    /// program can't be built, as there's no previous program to fall back to.
+    ///
+    /// Include names are relative to the directory of the main shader file, also in included
+    /// files: a `lib/a.glsl` next to the shader includes `lib/b.glsl`, not `b.glsl`.
+    pub fn new<C, P>(support: &mut C, shaders: &[(ShaderType, P)]) -> ReglResult<ReloadableProgram>
+        where C: ProgramCreationSupport,
+              P: AsRef<Path>
+    {
+        let shaders: Vec<(ShaderType, PathBuf)> =
+            shaders.iter().map(|&(t, ref p)| (t, p.as_ref().to_path_buf())).collect();
+        let (program, watched) = build(support, &shaders);
+        Ok(ReloadableProgram {
+            shaders: shaders,
+            program: try!(program),
+            watched: watched,
+            generation: 0,
+            last_error: None,
+        })
+    }
+
+    /// The most recent program that was built successfully.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Incremented every time the program is replaced.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Why the latest reload failed, or None if it succeeded.
+    pub fn last_error(&self) -> Option<&ReglError> {
+        self.last_error.as_ref()
+    }
+
+    /// Rebuilds the program if any of the files has been modified since the last build.
+    pub fn poll<C: ProgramCreationSupport>(&mut self, support: &mut C) -> ReloadStatus {
+        if self.watched.iter().all(|file| modified(&file.path) == file.modified) {
+            return ReloadStatus::Unchanged;
+        }
+        self.reload(support)
+    }
+
+    /// Rebuilds the program, whether or not the files have changed.
+    pub fn reload<C: ProgramCreationSupport>(&mut self, support: &mut C) -> ReloadStatus {
+        let (program, watched) = build(support, &self.shaders);
+        // Watch the files as they were for this attempt, so that a failed build isn't retried
+        // until the files change again
+        self.watched = watched;
+        match program {
+            Ok(program) => {
+                // The new program has a new uid, so the program tracker binds it on next use,
+                // even if the old one is still current when it's deleted here
+                self.program = program;
+                self.generation += 1;
+                self.last_error = None;
+                ReloadStatus::Reloaded
+            }
+            Err(error) => {
+                self.last_error = Some(error);
+                ReloadStatus::Failed
+            }
+        }
+    }
+}
+
+fn build<C: ProgramCreationSupport>(support: &mut C,
+                                    shaders: &[(ShaderType, PathBuf)])
+                                    -> (ReglResult<Program>, Vec<WatchedFile>) {
+    // Modification times are read before the sources, so that a change made while building
+    // triggers another reload
+    let mut watched = vec![];
+    for &(_, ref path) in shaders {
+        watched.push(WatchedFile {
+            path: path.clone(),
+            modified: modified(path),
+        });
+    }
+    let mut compiled = Vec::with_capacity(shaders.len());
+    for &(shader_type, ref path) in shaders {
+        match build_shader(support, shader_type, path, &mut watched) {
+            Ok(shader) => compiled.push(shader),
+            Err(error) => return (Err(error), watched),
+        }
+    }
+    (link(support, &compiled), watched)
+}
+
+/// Checks the compile and link status whether or not the support validates them, as a failed
+/// build must not replace the previous program.
+fn link<C: ProgramCreationSupport>(support: &mut C, compiled: &[Shader]) -> ReglResult<Program> {
+    for shader in compiled {
+        try!(shader::check_compiled(shader));
+    }
+    let pending = try!(ProgramBuilder::new(compiled).build_pending(support));
+    pending.finish()
+}
+
+fn build_shader<C: ProgramCreationSupport>(support: &mut C,
+                                           shader_type: ShaderType,
+                                           path: &Path,
+                                           watched: &mut Vec<WatchedFile>)
+                                           -> ReglResult<Shader> {
+    let preprocessed = try!(preprocess(path, watched));
+    Shader::from_preprocessed(support, shader_type, &preprocessed)
+}
+
+fn preprocess(path: &Path, watched: &mut Vec<WatchedFile>) -> ReglResult<PreprocessedSource> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            let message = format!("{}: {}", path.display(), error);
+            return Err(ReglError::ShaderSourceUnreadable(message));
+        }
+    };
+    let provider = WatchingIncludeProvider {
+        root: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        watched: RefCell::new(vec![]),
+    };
+    let preprocessed = ShaderPreprocessor::new()
+                           .include_provider(&provider)
+                           .preprocess(&path.to_string_lossy(), &source);
+    // Watch the files the preprocessor tried to load even if it failed, e.g. on a missing
+    // include
+    for included in provider.watched.into_inner() {
+        if !watched.iter().any(|file| file.path == included.path) {
+            watched.push(included);
+        }
+    }
+    preprocessed
+}
+
+/// Loads included files relative to the directory of the main shader, recording each file
+/// that is requested along with its modification time, read before its contents.
+#[derive(Debug)]
+struct WatchingIncludeProvider {
+    root: PathBuf,
+    watched: RefCell<Vec<WatchedFile>>,
+}
+
+impl IncludeProvider for WatchingIncludeProvider {
+    fn load(&self, name: &str) -> Option<String> {
+        let path = self.root.join(name);
+        let modified = modified(&path);
+        let source = fs::read_to_string(&path).ok();
+        let mut watched = self.watched.borrow_mut();
+        if !watched.iter().any(|file| file.path == path) {
+            watched.push(WatchedFile {
+                path: path,
+                modified: modified,
+            });
+        }
+        source
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use ReglError;
+    use super::{preprocess, WatchedFile};
+
+    /// A fresh directory with the given files, relative to it.
+    fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("regl-reload-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for &(file, contents) in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn watched_paths(watched: &[WatchedFile], root: &Path) -> Vec<PathBuf> {
+        watched.iter().map(|file| file.path.strip_prefix(root).unwrap().to_path_buf()).collect()
+    }
+
+    #[test]
+    fn resolves_nested_includes_relative_to_main_shader() {
+        let root = directory("nested",
+                             &[("main.glsl", "#include \"lib/a.glsl\"\nvoid main() {}"),
+                               ("lib/a.glsl", "#include \"lib/b.glsl\"\nfloat a;"),
+                               ("lib/b.glsl", "float b;")]);
+        let mut watched = vec![];
+        let preprocessed = preprocess(&root.join("main.glsl"), &mut watched).unwrap();
+        assert!(preprocessed.source().contains("float b;"));
+        assert_eq!(watched_paths(&watched, &root),
+                   vec![PathBuf::from("lib/a.glsl"), PathBuf::from("lib/b.glsl")]);
+        assert!(watched.iter().all(|file| file.modified.is_some()));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn watches_missing_includes() {
+        let root = directory("missing",
+                             &[("main.glsl", "#include \"lib/a.glsl\"\nvoid main() {}"),
+                               ("lib/a.glsl", "#include \"b.glsl\"\nfloat a;"),
+                               ("lib/b.glsl", "float b;")]);
+        let mut watched = vec![];
+        match preprocess(&root.join("main.glsl"), &mut watched) {
+            Err(ReglError::IncludeNotFound(ref name)) => assert_eq!(name, "b.glsl"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(watched_paths(&watched, &root),
+                   vec![PathBuf::from("lib/a.glsl"), PathBuf::from("b.glsl")]);
+        assert!(watched[1].modified.is_none());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
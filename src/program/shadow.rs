@@ -0,0 +1,349 @@
+
+use std::collections::HashMap;
+use gl::types::GLuint;
+use super::uniform::{self, UniformInfo, UniformType};
+
+/// How many uniform updates through the `Program::uniform_*` setters were passed to the GL,
+/// and how many were skipped because the uniform already had the value.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub struct UniformCacheStats {
+    pub updates: u64,
+    pub skipped: u64,
+}
+
+/// Shadow copy of the values of a program's global uniforms.
+#[derive(Debug)]
+pub struct UniformCache {
+    info: UniformInfo,
+    /// Every element of every global uniform, by location. Atomic counters can't be set and
+    /// aren't included.
+    elements: HashMap<i32, Element>,
+}
+
+#[derive(Debug)]
+struct Element {
+    uniform_type: UniformType,
+    /// Location of the next element of an array uniform.
+    next: Option<i32>,
+    /// The components of the value, as float bits or integers, with booleans as 0 or 1. None
+    /// if the value isn't known.
+    value: Option<Vec<u32>>,
+}
+
+/// A component of a uniform value.
+pub trait Component : Copy {
+    fn bits(self) -> u32;
+    fn truth(self) -> bool;
+}
+
+impl Component for f32 {
+    fn bits(self) -> u32 {
+        self.to_bits()
+    }
+
+    fn truth(self) -> bool {
+        self != 0.0
+    }
+}
+
+impl Component for i32 {
+    fn bits(self) -> u32 {
+        self as u32
+    }
+
+    fn truth(self) -> bool {
+        self != 0
+    }
+}
+
+impl Component for u32 {
+    fn bits(self) -> u32 {
+        self
+    }
+
+    fn truth(self) -> bool {
+        self != 0
+    }
+}
+
+impl UniformCache {
+    /// Introspects the uniforms of the program and reads their current values, which may have
+    /// been given by initializers in the shaders.
+    pub fn new(program_id: GLuint) -> UniformCache {
+        let info = uniform::get_uniform_info(program_id);
+        let mut elements = HashMap::new();
+        for global in &info.globals {
+            let components = match components(global.uniform_type) {
+                Some(components) => components,
+                None => continue,
+            };
+            let locations = element_locations(program_id, &global.name, global.location,
+                                              global.size);
+            for (i, &location) in locations.iter().enumerate() {
+                let value = read_value(program_id, location, global.uniform_type, components);
+                elements.insert(location,
+                                Element {
+                                    uniform_type: global.uniform_type,
+                                    next: locations.get(i + 1).cloned(),
+                                    value: Some(value),
+                                });
+            }
+        }
+        UniformCache {
+            info: info,
+            elements: elements,
+        }
+    }
+
+    pub fn info(&self) -> &UniformInfo {
+        &self.info
+    }
+
+    /// Whether setting `count` elements starting from the one at `location` to `values` would
+    /// leave them as they are. False whenever the GL would reject the update.
+    pub fn unchanged<T: Component>(&self,
+                                   location: i32,
+                                   uniform_type: UniformType,
+                                   count: u32,
+                                   values: &[T])
+                                   -> bool {
+        if count == 0 || values.is_empty() || values.len() % count as usize != 0 {
+            return false;
+        }
+        let locations = self.locations(location, count);
+        if locations.len() != count as usize {
+            return false;
+        }
+        let components = values.len() / count as usize;
+        locations.iter().zip(values.chunks(components)).all(|(location, values)| {
+            let element = &self.elements[location];
+            match element.value {
+                Some(ref value) if element.uniform_type == uniform_type &&
+                                   value.len() == values.len() => {
+                    value.iter().zip(values).all(|(&v, &c)| v == word(c, uniform_type))
+                }
+                _ => false,
+            }
+        })
+    }
+
+    /// Records the values of elements that have been set.
+    pub fn record<T: Component>(&mut self,
+                                location: i32,
+                                uniform_type: UniformType,
+                                count: u32,
+                                values: &[T]) {
+        if count == 0 || values.is_empty() || values.len() % count as usize != 0 {
+            // Not a value the GL accepts; forget what was there to be safe
+            return self.forget(location, count);
+        }
+        let components = values.len() / count as usize;
+        for (location, values) in self.locations(location, count)
+                                      .into_iter()
+                                      .zip(values.chunks(components)) {
+            let words = values.iter().map(|&c| word(c, uniform_type)).collect();
+            self.elements.get_mut(&location).unwrap().value = Some(words);
+        }
+    }
+
+    /// Marks the values of elements as unknown, so that they are set on the next update.
+    pub fn forget(&mut self, location: i32, count: u32) {
+        for location in self.locations(location, count) {
+            self.elements.get_mut(&location).unwrap().value = None;
+        }
+    }
+
+    /// Locations of up to `count` elements starting from `location`.
+    fn locations(&self, location: i32, count: u32) -> Vec<i32> {
+        let mut locations = vec![];
+        let mut next = Some(location);
+        while let Some(location) = next {
+            if locations.len() == count as usize || !self.elements.contains_key(&location) {
+                break;
+            }
+            locations.push(location);
+            next = self.elements[&location].next;
+        }
+        locations
+    }
+}
+
+fn word<T: Component>(component: T, uniform_type: UniformType) -> u32 {
+    if is_bool(uniform_type) {
+        component.truth() as u32
+    } else {
+        component.bits()
+    }
+}
+
+fn is_bool(uniform_type: UniformType) -> bool {
+    match uniform_type {
+        UniformType::Bool |
+        UniformType::BoolVec2 |
+        UniformType::BoolVec3 |
+        UniformType::BoolVec4 => true,
+        _ => false,
+    }
+}
+
+/// Number of components in one element, or None for uniforms that can't be set.
+fn components(uniform_type: UniformType) -> Option<usize> {
+    uniform::float_components(uniform_type)
+        .or_else(|_| uniform::int_components(uniform_type))
+        .or_else(|_| uniform::unsigned_int_components(uniform_type))
+        .ok()
+        .map(|components| components as usize)
+}
+
+/// The GL names an array uniform after its first element, e.g. `lights[0]`, and the other
+/// elements have locations of their own.
+fn element_locations(program_id: GLuint, name: &str, location: i32, size: i32) -> Vec<i32> {
+    let mut locations = vec![location];
+    if size > 1 && name.ends_with("[0]") {
+        let base = &name[..name.len() - "[0]".len()];
+        for index in 1..size {
+            match uniform::get_uniform_location(program_id, &format!("{}[{}]", base, index)) {
+                Ok(location) if location >= 0 => locations.push(location),
+                _ => break,
+            }
+        }
+    }
+    locations
+}
+
+fn read_value(program_id: GLuint,
+              location: i32,
+              uniform_type: UniformType,
+              components: usize)
+              -> Vec<u32> {
+    let mut value = vec![0u32; components];
+    if is_bool(uniform_type) || uniform::int_components(uniform_type).is_ok() {
+        glcall!(GetUniformiv(program_id, location, value.as_mut_ptr() as *mut i32));
+    } else if uniform::unsigned_int_components(uniform_type).is_ok() {
+        glcall!(GetUniformuiv(program_id, location, value.as_mut_ptr()));
+    } else {
+        glcall!(GetUniformfv(program_id, location, value.as_mut_ptr() as *mut f32));
+    }
+    if is_bool(uniform_type) {
+        for component in &mut value {
+            *component = (*component != 0) as u32;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{UniformCache, Element};
+    use super::super::uniform::{UniformInfo, UniformType};
+
+    /// A cache with a `vec2` at location 1, a `bvec2` at location 2 and a three element
+    /// `float` array at the out of order locations 5, 9 and 7. All values are known to be
+    /// zero.
+    fn cache() -> UniformCache {
+        let mut elements = HashMap::new();
+        let mut add = |location, uniform_type, next, components| {
+            elements.insert(location,
+                            Element {
+                                uniform_type: uniform_type,
+                                next: next,
+                                value: Some(vec![0; components]),
+                            });
+        };
+        add(1, UniformType::FloatVec2, None, 2);
+        add(2, UniformType::BoolVec2, None, 2);
+        add(5, UniformType::Float, Some(9), 1);
+        add(9, UniformType::Float, Some(7), 1);
+        add(7, UniformType::Float, None, 1);
+        UniformCache {
+            info: UniformInfo {
+                globals: vec![],
+                blocks: vec![],
+                atomic_counter_buffers: vec![],
+            },
+            elements: elements,
+        }
+    }
+
+    #[test]
+    fn compares_recorded_values() {
+        let mut cache = cache();
+        assert!(cache.unchanged(1, UniformType::FloatVec2, 1, &[0.0f32, 0.0]));
+        assert!(!cache.unchanged(1, UniformType::FloatVec2, 1, &[1.0f32, 2.0]));
+        cache.record(1, UniformType::FloatVec2, 1, &[1.0f32, 2.0]);
+        assert!(cache.unchanged(1, UniformType::FloatVec2, 1, &[1.0f32, 2.0]));
+        assert!(!cache.unchanged(1, UniformType::FloatVec2, 1, &[1.0f32, 2.5]));
+    }
+
+    #[test]
+    fn compares_floats_by_bits() {
+        let mut cache = cache();
+        assert!(!cache.unchanged(1, UniformType::FloatVec2, 1, &[-0.0f32, 0.0]));
+        cache.record(1, UniformType::FloatVec2, 1, &[f32::NAN, 0.0]);
+        assert!(cache.unchanged(1, UniformType::FloatVec2, 1, &[f32::NAN, 0.0]));
+    }
+
+    #[test]
+    fn rejects_mismatched_updates() {
+        let cache = cache();
+        // Wrong type, wrong number of components and unknown location
+        assert!(!cache.unchanged(1, UniformType::IntVec2, 1, &[0i32, 0]));
+        assert!(!cache.unchanged(1, UniformType::FloatVec2, 1, &[0.0f32]));
+        assert!(!cache.unchanged(3, UniformType::FloatVec2, 1, &[0.0f32, 0.0]));
+        // Values that can't be split evenly between the elements, and no elements at all
+        assert!(!cache.unchanged(5, UniformType::Float, 2, &[0.0f32, 0.0, 0.0]));
+        assert!(!cache.unchanged(5, UniformType::Float, 0, &[] as &[f32]));
+    }
+
+    #[test]
+    fn forgets_values_of_rejected_updates() {
+        let mut cache = cache();
+        cache.record(5, UniformType::Float, 2, &[1.0f32, 2.0, 3.0]);
+        assert!(!cache.unchanged(5, UniformType::Float, 1, &[0.0f32]));
+        assert!(!cache.unchanged(9, UniformType::Float, 1, &[0.0f32]));
+        assert!(cache.unchanged(7, UniformType::Float, 1, &[0.0f32]));
+    }
+
+    #[test]
+    fn follows_array_elements() {
+        let mut cache = cache();
+        cache.record(5, UniformType::Float, 3, &[1.0f32, 2.0, 3.0]);
+        assert!(cache.unchanged(5, UniformType::Float, 3, &[1.0f32, 2.0, 3.0]));
+        assert!(cache.unchanged(9, UniformType::Float, 2, &[2.0f32, 3.0]));
+        assert!(cache.unchanged(7, UniformType::Float, 1, &[3.0f32]));
+        assert!(!cache.unchanged(5, UniformType::Float, 3, &[1.0f32, 3.0, 2.0]));
+        // More elements than the array has from that location
+        assert!(!cache.unchanged(9, UniformType::Float, 3, &[2.0f32, 3.0, 0.0]));
+        assert_eq!(cache.locations(5, 3), vec![5, 9, 7]);
+        assert_eq!(cache.locations(9, 5), vec![9, 7]);
+    }
+
+    #[test]
+    fn records_elements_from_the_middle_of_an_array() {
+        let mut cache = cache();
+        cache.record(9, UniformType::Float, 2, &[2.0f32, 3.0]);
+        assert!(cache.unchanged(5, UniformType::Float, 3, &[0.0f32, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn normalizes_booleans() {
+        let mut cache = cache();
+        cache.record(2, UniformType::BoolVec2, 1, &[5i32, 0]);
+        assert!(cache.unchanged(2, UniformType::BoolVec2, 1, &[1i32, 0]));
+        assert!(cache.unchanged(2, UniformType::BoolVec2, 1, &[0.5f32, 0.0]));
+        assert!(cache.unchanged(2, UniformType::BoolVec2, 1, &[7u32, 0]));
+        assert!(!cache.unchanged(2, UniformType::BoolVec2, 1, &[0i32, 0]));
+    }
+
+    #[test]
+    fn forgets_values() {
+        let mut cache = cache();
+        cache.forget(9, 2);
+        assert!(cache.unchanged(5, UniformType::Float, 1, &[0.0f32]));
+        assert!(!cache.unchanged(9, UniformType::Float, 1, &[0.0f32]));
+        assert!(!cache.unchanged(7, UniformType::Float, 1, &[0.0f32]));
+        cache.record(5, UniformType::Float, 3, &[0.0f32, 0.0, 0.0]);
+        assert!(cache.unchanged(5, UniformType::Float, 3, &[0.0f32, 0.0, 0.0]));
+    }
+}
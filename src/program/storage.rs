@@ -0,0 +1,187 @@
+
+use std::ffi::CString;
+use gl::types::{GLenum, GLint, GLuint, GLsizei};
+use ReglResult;
+use ReglError;
+use super::UniformType;
+
+/// A variable of a shader storage block.
+#[derive(Debug)]
+pub struct BufferVariable {
+    /// Name of the variable, qualified with the names of the structs it is in.
+    pub name: String,
+    /// Data type of the variable.
+    pub variable_type: UniformType,
+    /// Length of the array, if the variable is an array. Zero for an unsized array.
+    pub size: i32,
+    /// How many bytes from the beginning of the block this variable is. See GL_OFFSET.
+    pub offset: i32,
+    /// For an array variable, the distance between each value in the array.
+    /// See GL_ARRAY_STRIDE.
+    pub array_stride: i32,
+    /// Distance between rows/cols of a matrix variable. See GL_MATRIX_STRIDE.
+    pub matrix_stride: i32,
+    /// Whether a matrix variable is stored in row major order.
+    pub row_major: bool,
+    /// If the variable is within an array of structs at the top level of the block, the length
+    /// of that array. Zero if the array is unsized. See GL_TOP_LEVEL_ARRAY_SIZE.
+    pub top_level_array_size: i32,
+    /// Distance between the elements of the top level array. See GL_TOP_LEVEL_ARRAY_STRIDE.
+    pub top_level_array_stride: i32,
+}
+
+impl BufferVariable {
+    /// Whether the variable is part of the unsized array at the end of the block, whose
+    /// length depends on the size of the bound buffer range.
+    pub fn in_unsized_array(&self) -> bool {
+        self.size == 0 || self.top_level_array_size == 0
+    }
+}
+
+/// Description of a shader storage block.
+#[derive(Debug)]
+pub struct StorageBlock {
+    /// Name of the block.
+    pub name: String,
+    /// Index of the block. Use this as the index, not the index in the vector this struct
+    /// is in!
+    pub index: u32,
+    /// The shader storage buffer binding point the block uses. See
+    /// `Program::storage_block_binding`.
+    pub binding: u32,
+    /// Minimum size of the buffer range bound to the block; with an unsized array, the size
+    /// when the array has one element. See GL_BUFFER_DATA_SIZE.
+    pub data_size: i32,
+    /// The variables contained by this block.
+    pub variables: Vec<BufferVariable>,
+}
+
+impl StorageBlock {
+    pub fn find<T: AsRef<str>>(&self, name: T) -> Option<&BufferVariable> {
+        self.variables.iter().find(|v| v.name == name.as_ref())
+    }
+
+    /// The variables of the unsized array at the end of the block, if it has one.
+    pub fn unsized_array(&self) -> Vec<&BufferVariable> {
+        self.variables.iter().filter(|v| v.in_unsized_array()).collect()
+    }
+}
+
+/// Top-level result structure for program's shader storage block introspection info.
+#[derive(Debug)]
+pub struct StorageBlockInfo {
+    pub blocks: Vec<StorageBlock>,
+}
+
+impl StorageBlockInfo {
+    pub fn find_block<T: AsRef<str>>(&self, name: T) -> Option<&StorageBlock> {
+        self.blocks.iter().find(|b| b.name == name.as_ref())
+    }
+}
+
+pub fn get_storage_block_info(program_id: GLuint) -> StorageBlockInfo {
+    let count = gl_interface_value(program_id,
+                                   ::gl::SHADER_STORAGE_BLOCK,
+                                   ::gl::ACTIVE_RESOURCES);
+    let blocks = (0..count as GLuint).map(|index| storage_block(program_id, index)).collect();
+    StorageBlockInfo { blocks: blocks }
+}
+
+fn storage_block(program_id: GLuint, index: GLuint) -> StorageBlock {
+    let interface = ::gl::SHADER_STORAGE_BLOCK;
+    let properties = [::gl::BUFFER_BINDING, ::gl::BUFFER_DATA_SIZE, ::gl::NUM_ACTIVE_VARIABLES];
+    let mut values = [0; 3];
+    gl_resource_properties(program_id, interface, index, &properties, &mut values);
+
+    let mut variable_indices = vec![0; values[2] as usize];
+    gl_resource_properties(program_id,
+                           interface,
+                           index,
+                           &[::gl::ACTIVE_VARIABLES],
+                           &mut variable_indices);
+    let mut variables: Vec<BufferVariable> =
+        variable_indices.iter().map(|&i| buffer_variable(program_id, i as GLuint)).collect();
+    variables.sort_by_key(|v| v.offset);
+
+    StorageBlock {
+        name: gl_resource_name(program_id, interface, index),
+        index: index,
+        binding: values[0] as u32,
+        data_size: values[1],
+        variables: variables,
+    }
+}
+
+fn buffer_variable(program_id: GLuint, index: GLuint) -> BufferVariable {
+    let interface = ::gl::BUFFER_VARIABLE;
+    let properties = [::gl::TYPE,
+                      ::gl::ARRAY_SIZE,
+                      ::gl::OFFSET,
+                      ::gl::ARRAY_STRIDE,
+                      ::gl::MATRIX_STRIDE,
+                      ::gl::IS_ROW_MAJOR,
+                      ::gl::TOP_LEVEL_ARRAY_SIZE,
+                      ::gl::TOP_LEVEL_ARRAY_STRIDE];
+    let mut values = [0; 8];
+    gl_resource_properties(program_id, interface, index, &properties, &mut values);
+    BufferVariable {
+        name: gl_resource_name(program_id, interface, index),
+        variable_type: (values[0] as GLenum).into(),
+        size: values[1],
+        offset: values[2],
+        array_stride: values[3],
+        matrix_stride: values[4],
+        row_major: values[5] != 0,
+        top_level_array_size: values[6],
+        top_level_array_stride: values[7],
+    }
+}
+
+pub fn set_storage_block_binding(program_id: GLuint, name: &str, binding: u32) -> ReglResult<()> {
+    let c_name = try!(CString::new(name));
+    let index = glcall!(GetProgramResourceIndex(program_id,
+                                                SHADER_STORAGE_BLOCK,
+                                                c_name.as_ptr()));
+    if index == ::gl::INVALID_INDEX {
+        return Err(ReglError::InactiveStorageBlock(name.to_string()));
+    }
+    glcall!(ShaderStorageBlockBinding(program_id, index, binding));
+    Ok(())
+}
+
+pub fn gl_interface_value(program_id: GLuint, interface: GLenum, property: GLenum) -> GLint {
+    let mut value = 0;
+    glcall!(GetProgramInterfaceiv(program_id, interface, property, &mut value));
+    value
+}
+
+pub fn gl_resource_properties(program_id: GLuint,
+                              interface: GLenum,
+                              index: GLuint,
+                              properties: &[GLenum],
+                              values: &mut [GLint]) {
+    let mut actual_length = 0;
+    glcall!(GetProgramResourceiv(program_id,
+                                 interface,
+                                 index,
+                                 properties.len() as GLsizei,
+                                 properties.as_ptr(),
+                                 values.len() as GLsizei,
+                                 &mut actual_length,
+                                 values.as_mut_ptr()));
+}
+
+fn gl_resource_name(program_id: GLuint, interface: GLenum, index: GLuint) -> String {
+    let mut length = [0];
+    gl_resource_properties(program_id, interface, index, &[::gl::NAME_LENGTH], &mut length);
+    let mut name_bytes = vec![0u8; length[0] as usize];
+    let name_bytes_ptr = name_bytes.as_mut_ptr() as *mut i8;
+    let mut actual_length = 0;
+    glcall!(GetProgramResourceName(program_id,
+                                   interface,
+                                   index,
+                                   name_bytes.len() as GLsizei,
+                                   &mut actual_length,
+                                   name_bytes_ptr));
+    String::from_utf8_lossy(&name_bytes[0..actual_length as usize]).into_owned()
+}
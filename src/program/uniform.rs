@@ -19,6 +19,12 @@ pub struct Uniform {
     pub uniform_type: UniformType,
     /// How many instances of the type this uniform contains. Length of an array so to speak.
     pub size: i32,
+    /// For an atomic counter, the index of the atomic counter buffer in
+    /// `UniformInfo::atomic_counter_buffers` it is stored in; -1 for other uniforms.
+    pub atomic_counter_buffer_index: i32,
+    /// For an atomic counter, how many bytes from the beginning of the atomic counter buffer
+    /// the counter is. See GL_UNIFORM_OFFSET.
+    pub offset: i32,
 }
 
 fn uniform(gl_uniform: GlUniform, location: i32) -> Uniform {
@@ -27,6 +33,8 @@ fn uniform(gl_uniform: GlUniform, location: i32) -> Uniform {
         location: location,
         uniform_type: (gl_uniform.uniform_type as GLenum).into(),
         size: gl_uniform.size,
+        atomic_counter_buffer_index: gl_uniform.atomic_counter_buffer_index,
+        offset: gl_uniform.offset,
     }
 }
 
@@ -51,6 +59,9 @@ pub struct InterfaceBlock {
     pub index: u32,
     /// See GL_UNIFORM_BLOCK_DATA_SIZE
     pub data_size: i32,
+    /// The uniform buffer binding point the block reads from. See
+    /// `Program::uniform_block_binding`.
+    pub binding: u32,
     /// The uniforms contained by this block.
     pub uniforms: Vec<BlockUniform>,
 }
@@ -80,13 +91,29 @@ pub struct BlockUniform {
     pub matrix_stride: i32,
 }
 
+/// Description of an atomic counter buffer, the storage shared by atomic counters declared
+/// with the same binding.
+#[derive(Debug)]
+pub struct AtomicCounterBuffer {
+    /// Index of the buffer, as in `Uniform::atomic_counter_buffer_index`.
+    pub index: u32,
+    /// The atomic counter buffer binding point, set with `layout(binding = N)` in the shader.
+    /// See `Buffer::bind_range`.
+    pub binding: u32,
+    /// Minimum size of the buffer range bound for the counters, in bytes. See
+    /// GL_ATOMIC_COUNTER_BUFFER_DATA_SIZE.
+    pub data_size: i32,
+}
+
 /// Top-level result structure for program's uniform introspection info.
 #[derive(Debug)]
 pub struct UniformInfo {
-    /// Global uniforms, not in interface blocks.
+    /// Global uniforms, not in interface blocks. Includes atomic counters.
     pub globals: Vec<Uniform>,
     /// Interface block definitions, may contain several uniforms themselves.
     pub blocks: Vec<InterfaceBlock>,
+    /// Atomic counter buffers used by the atomic counters in `globals`.
+    pub atomic_counter_buffers: Vec<AtomicCounterBuffer>,
 }
 
 impl UniformInfo {
@@ -99,6 +126,33 @@ impl UniformInfo {
     }
 }
 
+/// Finds the global uniform that `name` refers to, along with the index of the referred
+/// element if the uniform is an array. The GL names arrays after their first element, so
+/// `lights` and `lights[0]` both refer to the whole array `lights[0]`, and `lights[2]` to the
+/// array starting from its third element.
+pub fn find_global_element<'a>(info: &'a UniformInfo, name: &str) -> Option<(&'a Uniform, u32)> {
+    if let Some(uniform) = info.find_global(name) {
+        return Some((uniform, 0));
+    }
+    if let Some(uniform) = info.find_global(format!("{}[0]", name)) {
+        return Some((uniform, 0));
+    }
+    if !name.ends_with(']') {
+        return None;
+    }
+    let open = match name.rfind('[') {
+        Some(open) => open,
+        None => return None,
+    };
+    let index: u32 = match name[open + 1..name.len() - 1].parse() {
+        Ok(index) => index,
+        Err(_) => return None,
+    };
+    info.find_global(format!("{}[0]", &name[..open]))
+        .filter(|uniform| index < uniform.size as u32)
+        .map(|uniform| (uniform, index))
+}
+
 #[derive(Default)]
 struct GlUniform {
     name: String,
@@ -108,10 +162,18 @@ struct GlUniform {
     offset: i32,
     array_stride: i32,
     matrix_stride: i32,
+    atomic_counter_buffer_index: i32,
 }
 
 pub fn get_uniform_info(program_id: GLuint) -> UniformInfo {
     let gl_uniforms = get_gl_uniforms(program_id);
+    // Atomic counters are queried only when the program has some, to avoid errors on GL
+    // versions that don't know about them.
+    let atomic_counter_buffers = if gl_uniforms.iter().any(is_atomic_counter) {
+        get_atomic_counter_buffers(program_id)
+    } else {
+        vec![]
+    };
     let mut globals = vec![];
     let mut blocks = get_uniform_blocks(program_id);
     for gl_uniform in gl_uniforms.into_iter() {
@@ -130,9 +192,14 @@ pub fn get_uniform_info(program_id: GLuint) -> UniformInfo {
     UniformInfo {
         globals: globals,
         blocks: blocks,
+        atomic_counter_buffers: atomic_counter_buffers,
     }
 }
 
+fn is_atomic_counter(gl_uniform: &GlUniform) -> bool {
+    gl_uniform.uniform_type as GLenum == ::gl::UNSIGNED_INT_ATOMIC_COUNTER
+}
+
 fn get_gl_uniforms(program_id: GLuint) -> Vec<GlUniform> {
     let count = gl_program_value(program_id, ::gl::ACTIVE_UNIFORMS) as usize;
     if count == 0 {
@@ -148,6 +215,7 @@ fn get_gl_uniforms(program_id: GLuint) -> Vec<GlUniform> {
     let make_named: &Fn((usize, &i32)) -> GlUniform = &|(index, name_length)| {
         GlUniform {
             name: gl_uniform_name(program_id, index as GLuint, *name_length),
+            atomic_counter_buffer_index: -1,
             ..Default::default()
         }
     };
@@ -170,6 +238,15 @@ fn get_gl_uniforms(program_id: GLuint) -> Vec<GlUniform> {
         fill_info(::gl::UNIFORM_ARRAY_STRIDE, &|u| &mut u.array_stride);
         fill_info(::gl::UNIFORM_MATRIX_STRIDE, &|u| &mut u.matrix_stride);
     }
+    if uniforms.iter().any(is_atomic_counter) {
+        gl_uniform_properties(program_id,
+                              &indices,
+                              ::gl::UNIFORM_ATOMIC_COUNTER_BUFFER_INDEX,
+                              &mut intvalues);
+        for (gl_uniform, value) in uniforms.iter_mut().zip(intvalues.iter()) {
+            gl_uniform.atomic_counter_buffer_index = *value;
+        }
+    }
     uniforms
 }
 
@@ -205,12 +282,36 @@ fn get_uniform_blocks(program_id: GLuint) -> Vec<InterfaceBlock> {
                 index: index,
                 name: gl_block_name(program_id, index),
                 data_size: gl_block_property(program_id, index, ::gl::UNIFORM_BLOCK_DATA_SIZE),
+                binding: gl_block_property(program_id, index, ::gl::UNIFORM_BLOCK_BINDING) as u32,
                 uniforms: vec![],
             }
         })
         .collect()
 }
 
+fn get_atomic_counter_buffers(program_id: GLuint) -> Vec<AtomicCounterBuffer> {
+    let count = gl_program_value(program_id, ::gl::ACTIVE_ATOMIC_COUNTER_BUFFERS) as u32;
+    (0..count)
+        .map(|index| {
+            let property = |name| gl_atomic_counter_buffer_property(program_id, index, name);
+            AtomicCounterBuffer {
+                index: index,
+                binding: property(::gl::ATOMIC_COUNTER_BUFFER_BINDING) as u32,
+                data_size: property(::gl::ATOMIC_COUNTER_BUFFER_DATA_SIZE),
+            }
+        })
+        .collect()
+}
+
+fn gl_atomic_counter_buffer_property(program_id: GLuint,
+                                     buffer_index: GLuint,
+                                     property: GLenum)
+                                     -> i32 {
+    let mut value = 0;
+    glcall!(GetActiveAtomicCounterBufferiv(program_id, buffer_index, property, &mut value));
+    value
+}
+
 fn gl_block_name(program_id: GLuint, index: GLuint) -> String {
     let length = gl_block_property(program_id, index, ::gl::UNIFORM_BLOCK_NAME_LENGTH);
     let mut name_bytes = vec![0u8; length as usize];
@@ -235,6 +336,16 @@ pub fn get_uniform_location(program_id: GLuint, name: &str) -> ReglResult<i32> {
     Ok(glcall!(GetUniformLocation(program_id, c_name.as_ptr())))
 }
 
+pub fn set_uniform_block_binding(program_id: GLuint, name: &str, binding: u32) -> ReglResult<()> {
+    let c_name = try!(CString::new(name));
+    let index = glcall!(GetUniformBlockIndex(program_id, c_name.as_ptr()));
+    if index == ::gl::INVALID_INDEX {
+        return Err(ReglError::InactiveUniformBlock(name.to_string()));
+    }
+    glcall!(UniformBlockBinding(program_id, index, binding));
+    Ok(())
+}
+
 pub fn uniform_value_f32(location: i32,
                          uniform_type: UniformType,
                          count: u32,
@@ -253,13 +364,7 @@ pub fn uniform_value_f32(location: i32,
             return uniform_value_matrix(location, uniform_type, count, values, false),
         _ => (),
     }
-    let components = match uniform_type {
-        UniformType::Bool | UniformType::Float => 1,
-        UniformType::BoolVec2 | UniformType::FloatVec2 => 2,
-        UniformType::BoolVec3 | UniformType::FloatVec3 => 3,
-        UniformType::BoolVec4 | UniformType::FloatVec4 => 4,
-        _ => return Err(ReglError::UniformTypeMismatch),
-    };
+    let components = try!(float_components(uniform_type));
     let count = count as i32;
     match components {
         1 => glcall!(Uniform1fv(location, count, values.as_ptr())),
@@ -276,51 +381,7 @@ pub fn uniform_value_i32(location: i32,
                          count: u32,
                          values: &[i32])
                          -> ReglResult<()> {
-    let components = match uniform_type {
-        UniformType::Bool | UniformType::Int => 1,
-        UniformType::BoolVec2 | UniformType::IntVec2 => 2,
-        UniformType::BoolVec3 | UniformType::IntVec3 => 3,
-        UniformType::BoolVec4 | UniformType::IntVec4 => 4,
-
-        UniformType::Sampler1d |
-        UniformType::Sampler2d |
-        UniformType::Sampler3d |
-        UniformType::SamplerCube |
-        UniformType::Sampler1dShadow |
-        UniformType::Sampler2dShadow |
-        UniformType::Sampler1dArray |
-        UniformType::Sampler2dArray |
-        UniformType::Sampler1dArrayShadow |
-        UniformType::Sampler2dArrayShadow |
-        UniformType::Sampler2dMultisample |
-        UniformType::Sampler2dMultisampleArray |
-        UniformType::SamplerCubeShadow |
-        UniformType::SamplerBuffer |
-        UniformType::Sampler2dRect |
-        UniformType::Sampler2dRectShadow |
-        UniformType::IntSampler1d |
-        UniformType::IntSampler2d |
-        UniformType::IntSampler3d |
-        UniformType::IntSamplerCube |
-        UniformType::IntSampler1dArray |
-        UniformType::IntSampler2dArray |
-        UniformType::IntSampler2dMultisample |
-        UniformType::IntSampler2dMultisampleArray |
-        UniformType::IntSamplerBuffer |
-        UniformType::IntSampler2dRect |
-        UniformType::UnsignedIntSampler1d |
-        UniformType::UnsignedIntSampler2d |
-        UniformType::UnsignedIntSampler3d |
-        UniformType::UnsignedIntSamplerCube |
-        UniformType::UnsignedIntSampler1dArray |
-        UniformType::UnsignedIntSampler2dArray |
-        UniformType::UnsignedIntSampler2dMultisample |
-        UniformType::UnsignedIntSampler2dMultisampleArray |
-        UniformType::UnsignedIntSamplerBuffer |
-        UniformType::UnsignedIntSampler2dRect => 1,
-
-        _ => return Err(ReglError::UniformTypeMismatch),
-    };
+    let components = try!(int_components(uniform_type));
     try!(check_uniform_element_count(components, count, values));
     let count = count as i32;
     match components {
@@ -338,13 +399,7 @@ pub fn uniform_value_u32(location: i32,
                          count: u32,
                          values: &[u32])
                          -> ReglResult<()> {
-    let components = match uniform_type {
-        UniformType::Bool | UniformType::UnsignedInt => 1,
-        UniformType::BoolVec2 | UniformType::UnsignedIntVec2 => 2,
-        UniformType::BoolVec3 | UniformType::UnsignedIntVec3 => 3,
-        UniformType::BoolVec4 | UniformType::UnsignedIntVec4 => 4,
-        _ => return Err(ReglError::UniformTypeMismatch),
-    };
+    let components = try!(unsigned_int_components(uniform_type));
     try!(check_uniform_element_count(components, count, values));
     let count = count as i32;
     match components {
@@ -368,18 +423,7 @@ pub fn uniform_value_matrix(location: i32,
     } else {
         ::gl::FALSE
     };
-    let components = match uniform_type {
-        UniformType::FloatMat2 => 2 * 2,
-        UniformType::FloatMat3 => 3 * 3,
-        UniformType::FloatMat4 => 4 * 4,
-        UniformType::FloatMat2x3 => 2 * 3,
-        UniformType::FloatMat2x4 => 2 * 4,
-        UniformType::FloatMat3x2 => 3 * 2,
-        UniformType::FloatMat3x4 => 3 * 4,
-        UniformType::FloatMat4x2 => 4 * 2,
-        UniformType::FloatMat4x3 => 4 * 3,
-        _ => return Err(ReglError::UniformTypeMismatch),
-    };
+    let components = try!(matrix_components(uniform_type));
     try!(check_uniform_element_count(components, count, values));
     let count = count as i32;
     match uniform_type {
@@ -406,6 +450,98 @@ pub fn uniform_value_matrix(location: i32,
     Ok(())
 }
 
+/// Number of components in one element of a uniform set with `uniform_value_f32`.
+pub fn float_components(uniform_type: UniformType) -> ReglResult<u32> {
+    match uniform_type {
+        UniformType::Bool | UniformType::Float => Ok(1),
+        UniformType::BoolVec2 | UniformType::FloatVec2 => Ok(2),
+        UniformType::BoolVec3 | UniformType::FloatVec3 => Ok(3),
+        UniformType::BoolVec4 | UniformType::FloatVec4 => Ok(4),
+        _ => matrix_components(uniform_type),
+    }
+}
+
+/// Number of components in one element of a uniform set with `uniform_value_i32`.
+pub fn int_components(uniform_type: UniformType) -> ReglResult<u32> {
+    match uniform_type {
+        UniformType::Bool | UniformType::Int => Ok(1),
+        UniformType::BoolVec2 | UniformType::IntVec2 => Ok(2),
+        UniformType::BoolVec3 | UniformType::IntVec3 => Ok(3),
+        UniformType::BoolVec4 | UniformType::IntVec4 => Ok(4),
+        t if is_sampler(t) => Ok(1),
+        _ => Err(ReglError::UniformTypeMismatch),
+    }
+}
+
+/// Number of components in one element of a uniform set with `uniform_value_u32`.
+pub fn unsigned_int_components(uniform_type: UniformType) -> ReglResult<u32> {
+    match uniform_type {
+        UniformType::Bool | UniformType::UnsignedInt => Ok(1),
+        UniformType::BoolVec2 | UniformType::UnsignedIntVec2 => Ok(2),
+        UniformType::BoolVec3 | UniformType::UnsignedIntVec3 => Ok(3),
+        UniformType::BoolVec4 | UniformType::UnsignedIntVec4 => Ok(4),
+        _ => Err(ReglError::UniformTypeMismatch),
+    }
+}
+
+/// Number of components in one element of a uniform set with `uniform_value_matrix`.
+pub fn matrix_components(uniform_type: UniformType) -> ReglResult<u32> {
+    match uniform_type {
+        UniformType::FloatMat2 => Ok(2 * 2),
+        UniformType::FloatMat3 => Ok(3 * 3),
+        UniformType::FloatMat4 => Ok(4 * 4),
+        UniformType::FloatMat2x3 => Ok(2 * 3),
+        UniformType::FloatMat2x4 => Ok(2 * 4),
+        UniformType::FloatMat3x2 => Ok(3 * 2),
+        UniformType::FloatMat3x4 => Ok(3 * 4),
+        UniformType::FloatMat4x2 => Ok(4 * 2),
+        UniformType::FloatMat4x3 => Ok(4 * 3),
+        _ => Err(ReglError::UniformTypeMismatch),
+    }
+}
+
+pub fn is_sampler(uniform_type: UniformType) -> bool {
+    match uniform_type {
+        UniformType::Sampler1d |
+        UniformType::Sampler2d |
+        UniformType::Sampler3d |
+        UniformType::SamplerCube |
+        UniformType::Sampler1dShadow |
+        UniformType::Sampler2dShadow |
+        UniformType::Sampler1dArray |
+        UniformType::Sampler2dArray |
+        UniformType::Sampler1dArrayShadow |
+        UniformType::Sampler2dArrayShadow |
+        UniformType::Sampler2dMultisample |
+        UniformType::Sampler2dMultisampleArray |
+        UniformType::SamplerCubeShadow |
+        UniformType::SamplerBuffer |
+        UniformType::Sampler2dRect |
+        UniformType::Sampler2dRectShadow |
+        UniformType::IntSampler1d |
+        UniformType::IntSampler2d |
+        UniformType::IntSampler3d |
+        UniformType::IntSamplerCube |
+        UniformType::IntSampler1dArray |
+        UniformType::IntSampler2dArray |
+        UniformType::IntSampler2dMultisample |
+        UniformType::IntSampler2dMultisampleArray |
+        UniformType::IntSamplerBuffer |
+        UniformType::IntSampler2dRect |
+        UniformType::UnsignedIntSampler1d |
+        UniformType::UnsignedIntSampler2d |
+        UniformType::UnsignedIntSampler3d |
+        UniformType::UnsignedIntSamplerCube |
+        UniformType::UnsignedIntSampler1dArray |
+        UniformType::UnsignedIntSampler2dArray |
+        UniformType::UnsignedIntSampler2dMultisample |
+        UniformType::UnsignedIntSampler2dMultisampleArray |
+        UniformType::UnsignedIntSamplerBuffer |
+        UniformType::UnsignedIntSampler2dRect => true,
+        _ => false,
+    }
+}
+
 fn check_uniform_element_count<T>(components: u32, count: u32, values: &[T]) -> ReglResult<()> {
     if components as usize * count as usize == values.len() {
         Ok(())
@@ -414,7 +550,7 @@ fn check_uniform_element_count<T>(components: u32, count: u32, values: &[T]) ->
     }
 }
 
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum UniformType {
     Float,
     FloatVec2,
@@ -477,6 +613,7 @@ pub enum UniformType {
     UnsignedIntSampler2dMultisampleArray,
     UnsignedIntSamplerBuffer,
     UnsignedIntSampler2dRect,
+    UnsignedIntAtomicCounter,
     UnrecognizedType(u32),
 }
 
@@ -546,7 +683,77 @@ impl From<GLenum> for UniformType {
                 UniformType::UnsignedIntSampler2dMultisampleArray,
             ::gl::UNSIGNED_INT_SAMPLER_BUFFER => UniformType::UnsignedIntSamplerBuffer,
             ::gl::UNSIGNED_INT_SAMPLER_2D_RECT => UniformType::UnsignedIntSampler2dRect,
+            ::gl::UNSIGNED_INT_ATOMIC_COUNTER => UniformType::UnsignedIntAtomicCounter,
             other => UniformType::UnrecognizedType(other),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{find_global_element, Uniform, UniformInfo, UniformType};
+
+    fn info(globals: &[(&str, i32)]) -> UniformInfo {
+        UniformInfo {
+            globals: globals.iter()
+                            .enumerate()
+                            .map(|(i, &(name, size))| {
+                                Uniform {
+                                    name: name.to_string(),
+                                    location: i as i32,
+                                    uniform_type: UniformType::FloatVec4,
+                                    size: size,
+                                    atomic_counter_buffer_index: -1,
+                                    offset: -1,
+                                }
+                            })
+                            .collect(),
+            blocks: vec![],
+            atomic_counter_buffers: vec![],
+        }
+    }
+
+    fn find(info: &UniformInfo, name: &str) -> Option<(String, u32)> {
+        find_global_element(info, name).map(|(uniform, index)| (uniform.name.clone(), index))
+    }
+
+    #[test]
+    fn finds_plain_uniforms() {
+        let info = info(&[("color", 1)]);
+        assert_eq!(find(&info, "color"), Some(("color".to_string(), 0)));
+        assert_eq!(find(&info, "colour"), None);
+        assert_eq!(find(&info, "color[0]"), None);
+    }
+
+    #[test]
+    fn finds_arrays_by_name_and_first_element() {
+        let info = info(&[("lights[0]", 4)]);
+        assert_eq!(find(&info, "lights"), Some(("lights[0]".to_string(), 0)));
+        assert_eq!(find(&info, "lights[0]"), Some(("lights[0]".to_string(), 0)));
+    }
+
+    #[test]
+    fn finds_array_elements() {
+        let info = info(&[("lights[0]", 4)]);
+        assert_eq!(find(&info, "lights[2]"), Some(("lights[0]".to_string(), 2)));
+        assert_eq!(find(&info, "lights[3]"), Some(("lights[0]".to_string(), 3)));
+        assert_eq!(find(&info, "lights[4]"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_indices() {
+        let info = info(&[("lights[0]", 4)]);
+        assert_eq!(find(&info, "lights[]"), None);
+        assert_eq!(find(&info, "lights[-1]"), None);
+        assert_eq!(find(&info, "lights[x]"), None);
+        assert_eq!(find(&info, "lights1]"), None);
+        assert_eq!(find(&info, "lamps[1]"), None);
+    }
+
+    #[test]
+    fn finds_elements_of_nested_names() {
+        let info = info(&[("material.colors[0]", 3)]);
+        assert_eq!(find(&info, "material.colors[1]"),
+                   Some(("material.colors[0]".to_string(), 1)));
+    }
+}
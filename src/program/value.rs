@@ -0,0 +1,194 @@
+
+use std::mem::size_of;
+use std::slice;
+use ReglResult;
+use super::{Program, UniformType};
+
+/// A value that can be set to a uniform with `Program::set_uniform`: a single element, or a
+/// slice of elements for an array uniform.
+pub trait UniformValue {
+    /// Type of the uniform that holds one element of the value.
+    fn element_type(&self) -> UniformType;
+    /// How many elements the value has.
+    fn len(&self) -> u32;
+    /// Sets the value to the uniform at `location`, whose type has been checked to accept it.
+    fn set(&self, program: &Program, location: i32, uniform_type: UniformType) -> ReglResult<()>;
+}
+
+/// A Rust type corresponding to a GLSL type. Vectors are arrays of their components and
+/// matrices arrays of their columns, e.g. `[[f32; 4]; 4]` for a `mat4`.
+pub trait UniformElement : Copy {
+    fn uniform_type() -> UniformType;
+    /// Sets the elements using one of the `Program::uniform_*` setters.
+    fn set_elements(program: &Program,
+                    location: i32,
+                    uniform_type: UniformType,
+                    values: &[Self])
+                    -> ReglResult<()>;
+}
+
+impl<T: UniformElement> UniformValue for T {
+    fn element_type(&self) -> UniformType {
+        T::uniform_type()
+    }
+
+    fn len(&self) -> u32 {
+        1
+    }
+
+    fn set(&self, program: &Program, location: i32, uniform_type: UniformType) -> ReglResult<()> {
+        T::set_elements(program, location, uniform_type, slice::from_ref(self))
+    }
+}
+
+impl<T: UniformElement> UniformValue for [T] {
+    fn element_type(&self) -> UniformType {
+        T::uniform_type()
+    }
+
+    fn len(&self) -> u32 {
+        <[T]>::len(self) as u32
+    }
+
+    fn set(&self, program: &Program, location: i32, uniform_type: UniformType) -> ReglResult<()> {
+        T::set_elements(program, location, uniform_type, self)
+    }
+}
+
+/// Whether a uniform of `uniform_type` can be set with elements of `element_type`. Samplers
+/// are set with the index of a texture unit.
+pub fn accepts(uniform_type: UniformType, element_type: UniformType) -> bool {
+    uniform_type == element_type ||
+    (element_type == UniformType::Int && super::uniform::is_sampler(uniform_type))
+}
+
+/// Reinterprets a slice of arrays as a slice of their components.
+///
+/// Unsafe because `T` must consist of exactly `components` values of `U`, e.g. `[f32; 4]` and
+/// `f32` with 4 components. The sizes are checked, which catches a wrong count of components.
+unsafe fn flatten<T: Copy, U: Copy>(values: &[T], components: usize) -> &[U] {
+    assert_eq!(size_of::<T>(), components * size_of::<U>());
+    slice::from_raw_parts(values.as_ptr() as *const U, values.len() * components)
+}
+
+macro_rules! uniform_element {
+    ($t:ty, $uniform_type:ident, $components:expr, $setter:ident) => {
+        impl UniformElement for $t {
+            fn uniform_type() -> UniformType {
+                UniformType::$uniform_type
+            }
+
+            fn set_elements(program: &Program,
+                            location: i32,
+                            uniform_type: UniformType,
+                            values: &[Self])
+                            -> ReglResult<()> {
+                program.$setter(location,
+                                uniform_type,
+                                values.len() as u32,
+                                unsafe { flatten(values, $components) })
+            }
+        }
+    }
+}
+
+uniform_element!(f32, Float, 1, uniform_f32);
+uniform_element!([f32; 2], FloatVec2, 2, uniform_f32);
+uniform_element!([f32; 3], FloatVec3, 3, uniform_f32);
+uniform_element!([f32; 4], FloatVec4, 4, uniform_f32);
+uniform_element!(i32, Int, 1, uniform_i32);
+uniform_element!([i32; 2], IntVec2, 2, uniform_i32);
+uniform_element!([i32; 3], IntVec3, 3, uniform_i32);
+uniform_element!([i32; 4], IntVec4, 4, uniform_i32);
+uniform_element!(u32, UnsignedInt, 1, uniform_u32);
+uniform_element!([u32; 2], UnsignedIntVec2, 2, uniform_u32);
+uniform_element!([u32; 3], UnsignedIntVec3, 3, uniform_u32);
+uniform_element!([u32; 4], UnsignedIntVec4, 4, uniform_u32);
+
+macro_rules! bool_uniform_element {
+    ($t:ty, $uniform_type:ident, $components:expr) => {
+        impl UniformElement for $t {
+            fn uniform_type() -> UniformType {
+                UniformType::$uniform_type
+            }
+
+            fn set_elements(program: &Program,
+                            location: i32,
+                            uniform_type: UniformType,
+                            values: &[Self])
+                            -> ReglResult<()> {
+                let flat: &[bool] = unsafe { flatten(values, $components) };
+                let ints: Vec<i32> = flat.iter().map(|&b| b as i32).collect();
+                program.uniform_i32(location, uniform_type, values.len() as u32, &ints)
+            }
+        }
+    }
+}
+
+bool_uniform_element!(bool, Bool, 1);
+bool_uniform_element!([bool; 2], BoolVec2, 2);
+bool_uniform_element!([bool; 3], BoolVec3, 3);
+bool_uniform_element!([bool; 4], BoolVec4, 4);
+
+macro_rules! matrix_uniform_element {
+    ($t:ty, $uniform_type:ident, $components:expr) => {
+        impl UniformElement for $t {
+            fn uniform_type() -> UniformType {
+                UniformType::$uniform_type
+            }
+
+            fn set_elements(program: &Program,
+                            location: i32,
+                            uniform_type: UniformType,
+                            values: &[Self])
+                            -> ReglResult<()> {
+                program.uniform_matrix(location,
+                                       uniform_type,
+                                       values.len() as u32,
+                                       unsafe { flatten(values, $components) },
+                                       false)
+            }
+        }
+    }
+}
+
+matrix_uniform_element!([[f32; 2]; 2], FloatMat2, 2 * 2);
+matrix_uniform_element!([[f32; 3]; 3], FloatMat3, 3 * 3);
+matrix_uniform_element!([[f32; 4]; 4], FloatMat4, 4 * 4);
+
+#[cfg(test)]
+mod tests {
+    use super::{accepts, flatten};
+    use super::super::UniformType;
+
+    #[test]
+    fn accepts_matching_types() {
+        assert!(accepts(UniformType::FloatVec3, UniformType::FloatVec3));
+        assert!(accepts(UniformType::FloatMat4, UniformType::FloatMat4));
+        assert!(!accepts(UniformType::FloatVec3, UniformType::FloatVec4));
+        assert!(!accepts(UniformType::Float, UniformType::Int));
+        assert!(!accepts(UniformType::Int, UniformType::Bool));
+    }
+
+    #[test]
+    fn accepts_texture_units_for_samplers() {
+        assert!(accepts(UniformType::Sampler2d, UniformType::Int));
+        assert!(accepts(UniformType::SamplerCube, UniformType::Int));
+        assert!(!accepts(UniformType::Sampler2d, UniformType::UnsignedInt));
+        assert!(!accepts(UniformType::Int, UniformType::Sampler2d));
+    }
+
+    #[test]
+    fn flattens_arrays() {
+        let values = [[1.0f32, 2.0], [3.0, 4.0]];
+        let flat: &[f32] = unsafe { flatten(&values, 2) };
+        assert_eq!(flat, &[1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_wrong_component_count() {
+        let values = [[1.0f32, 2.0, 3.0]];
+        let _: &[f32] = unsafe { flatten(&values, 4) };
+    }
+}
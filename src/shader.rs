@@ -3,6 +3,8 @@ use std::ffi::CString;
 use gl::types::{GLenum, GLint};
 use id::GlId;
 use resource::ResourceCreationSupport;
+use preprocessor::{PreprocessedSource, LineMap};
+use diagnostic::InfoLog;
 use ReglResult;
 use ReglError;
 
@@ -14,10 +16,15 @@ pub trait InternalShader {
     fn gl_id(&self) -> GlId;
 }
 
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
 pub enum ShaderType {
     VertexShader,
+    TessControlShader,
+    TessEvaluationShader,
+    GeometryShader,
     FragmentShader,
+    /// Can only be linked into a program on its own.
+    ComputeShader,
 }
 
 #[derive(Debug,Clone,Copy)]
@@ -26,25 +33,49 @@ pub struct ShaderSource<'a>(pub ShaderType, pub &'a str);
 #[derive(Debug)]
 pub struct Shader {
     gl_id: GlId,
+    shader_type: ShaderType,
+    source: String,
+    line_map: Option<LineMap>,
 }
 
 impl Shader {
     pub fn new<C: ShaderCreationSupport>(support: &mut C,
                                          shader_source: &ShaderSource)
                                          -> ReglResult<Shader> {
-        let gl_id = glcall!(CreateShader(gl_shader_type(shader_source.0)));
+        compile(support.validate_after_compilation(),
+                shader_source.0,
+                shader_source.1,
+                None)
+    }
 
-        try!(add_shader_source(gl_id, shader_source.1));
-        glcall!(CompileShader(gl_id));
-        if support.validate_after_compilation() && !compiled(gl_id) {
-            return Err(ReglError::ShaderCompilationError(info_log(gl_id)));
-        }
+    /// Compiles source prepared by a `ShaderPreprocessor`. Line numbers in the errors and the
+    /// info log refer to the original files.
+    pub fn from_preprocessed<C: ShaderCreationSupport>(support: &mut C,
+                                                       shader_type: ShaderType,
+                                                       source: &PreprocessedSource)
+                                                       -> ReglResult<Shader> {
+        compile(support.validate_after_compilation(),
+                shader_type,
+                source.source(),
+                Some(source.line_map().clone()))
+    }
 
-        Ok(Shader { gl_id: gl_id })
+    pub fn shader_type(&self) -> ShaderType {
+        self.shader_type
     }
 
-    pub fn info_log(&self) -> String {
-        info_log(self.gl_id)
+    /// The source the shader was compiled from, after preprocessing.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The compiler's log, including warnings of a successful compilation.
+    pub fn info_log(&self) -> InfoLog {
+        let log = info_log(self.gl_id);
+        match self.line_map {
+            Some(ref line_map) => InfoLog::parse_mapped(log, line_map),
+            None => InfoLog::parse(log),
+        }
     }
 }
 
@@ -60,6 +91,40 @@ impl Drop for Shader {
     }
 }
 
+/// Submits the source for compilation without waiting for the result. See `check_compiled`.
+pub fn compile_unchecked(shader_source: &ShaderSource) -> ReglResult<Shader> {
+    compile(false, shader_source.0, shader_source.1, None)
+}
+
+pub fn check_compiled(shader: &Shader) -> ReglResult<()> {
+    if compiled(shader.gl_id) {
+        Ok(())
+    } else {
+        Err(ReglError::ShaderCompilationError(shader.info_log()))
+    }
+}
+
+fn compile(validate: bool,
+           shader_type: ShaderType,
+           source: &str,
+           line_map: Option<LineMap>)
+           -> ReglResult<Shader> {
+    let gl_id = glcall!(CreateShader(gl_shader_type(shader_type)));
+    let shader = Shader {
+        gl_id: gl_id,
+        shader_type: shader_type,
+        source: source.to_string(),
+        line_map: line_map,
+    };
+
+    try!(add_shader_source(gl_id, source));
+    glcall!(CompileShader(gl_id));
+    if validate {
+        try!(check_compiled(&shader));
+    }
+    Ok(shader)
+}
+
 fn add_shader_source(gl_id: GlId, source: &str) -> ReglResult<()> {
     let c_source = try!(CString::new(source));
     let len = c_source.to_bytes().len() as GLint;
@@ -92,6 +157,10 @@ fn info_log(gl_id: GlId) -> String {
 fn gl_shader_type(shader_type: ShaderType) -> GLenum {
     match shader_type {
         ShaderType::VertexShader => ::gl::VERTEX_SHADER,
+        ShaderType::TessControlShader => ::gl::TESS_CONTROL_SHADER,
+        ShaderType::TessEvaluationShader => ::gl::TESS_EVALUATION_SHADER,
+        ShaderType::GeometryShader => ::gl::GEOMETRY_SHADER,
         ShaderType::FragmentShader => ::gl::FRAGMENT_SHADER,
+        ShaderType::ComputeShader => ::gl::COMPUTE_SHADER,
     }
 }
@@ -0,0 +1,168 @@
+
+use std::collections::VecDeque;
+use std::mem::{align_of, size_of};
+use std::ptr;
+use ReglResult;
+use ReglError;
+use pod::Pod;
+use sync::Fence;
+use buffer::{Buffer, BufferCreationSupport, BufferTarget, IndexedBufferTarget,
+             create_immutable_buffer, get_base_buffer};
+
+/// A byte range of a `StreamBuffer` handed out by `StreamBuffer::push`.
+#[derive(Debug,Clone,Copy)]
+pub struct StreamRange {
+    /// Offset from the beginning of the buffer, in bytes. Use this as the
+    /// `VertexAttribute::offset`, the `index_offset` of an indexed draw or the offset of a
+    /// uniform block range binding.
+    pub offset: usize,
+    /// Length of the range in bytes.
+    pub len: usize,
+}
+
+#[derive(Debug)]
+struct FencedRegion {
+    start: usize,
+    /// The region may wrap around the end of the buffer.
+    len: usize,
+    fence: Fence,
+}
+
+/// A ring buffer for streaming per-frame data to the GL.
+///
+/// The buffer has immutable storage that stays persistently mapped, so data is written
+/// straight into GPU visible memory. Data pushed during a frame stays valid until the GL is
+/// done with it: `end_frame` puts a fence after the frame's draw calls, and the region is not
+/// reused before the fence is signaled.
+#[derive(Debug)]
+pub struct StreamBuffer {
+    buffer: Buffer,
+    ptr: *mut u8,
+    alignment: usize,
+    head: usize,
+    frame_start: usize,
+    frame_len: usize,
+    pending: VecDeque<FencedRegion>,
+}
+
+impl StreamBuffer {
+    /// Creates a stream buffer of `data_len` bytes. For uniform and shader storage buffers,
+    /// ranges are aligned so that they can be bound with `Buffer::bind_range`.
+    pub fn new<C: BufferCreationSupport>(support: &mut C,
+                                         target: BufferTarget,
+                                         data_len: usize)
+                                         -> ReglResult<StreamBuffer> {
+        let flags = ::gl::MAP_WRITE_BIT | ::gl::MAP_PERSISTENT_BIT | ::gl::MAP_COHERENT_BIT;
+        let buffer = create_immutable_buffer(support, target, data_len, flags);
+        let ptr = get_base_buffer(&buffer).map_range(0, data_len, flags) as *mut u8;
+        if ptr.is_null() {
+            return Err(ReglError::BufferMappingFailed);
+        }
+        let alignment = match target {
+            BufferTarget::UniformBuffer =>
+                buffer.offset_alignment(IndexedBufferTarget::UniformBuffer),
+            BufferTarget::ShaderStorageBuffer =>
+                buffer.offset_alignment(IndexedBufferTarget::ShaderStorageBuffer),
+            _ => 1,
+        };
+        Ok(StreamBuffer {
+            buffer: buffer,
+            ptr: ptr,
+            alignment: alignment,
+            head: 0,
+            frame_start: 0,
+            frame_len: 0,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Copies `data` into the buffer and returns where it was put. Waits for the GL if the
+    /// space is still in use by earlier frames. Fails if the data doesn't fit in the buffer
+    /// alongside the data already pushed during this frame, or if waiting for the GL fails.
+    pub fn push<T: Pod>(&mut self, data: &[T]) -> ReglResult<StreamRange> {
+        let len = data.len() * size_of::<T>();
+        let range = try!(self.allocate(len, align_of::<T>()));
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr() as *const u8,
+                                     self.ptr.offset(range.offset as isize),
+                                     len);
+        }
+        Ok(range)
+    }
+
+    /// Marks the end of the data used by the draw calls issued so far. Call after the last
+    /// draw call of a frame that reads from the buffer.
+    pub fn end_frame(&mut self) {
+        if self.frame_len == 0 {
+            return;
+        }
+        self.pending.push_back(FencedRegion {
+            start: self.frame_start,
+            len: self.frame_len,
+            fence: Fence::new(),
+        });
+        self.frame_start = self.head;
+        self.frame_len = 0;
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn allocate(&mut self, len: usize, alignment: usize) -> ReglResult<StreamRange> {
+        let size = self.buffer.data_len();
+        let alignment = lcm(alignment, self.alignment);
+        let mut offset = align(self.head, alignment);
+        if offset + len > size {
+            offset = 0;
+        }
+        let used = self.frame_len + wrapped_distance(self.head, offset, size) + len;
+        if len > size || used > size {
+            return Err(ReglError::StreamBufferFull);
+        }
+        let end = offset + len;
+        while self.pending.iter().any(|region| overlaps(region, offset, end, size)) {
+            while !try!(self.pending.front().unwrap().fence.wait(1_000_000_000)) {}
+            self.pending.pop_front();
+        }
+        self.head = end;
+        self.frame_len = used;
+        Ok(StreamRange {
+            offset: offset,
+            len: len,
+        })
+    }
+}
+
+/// Number of bytes skipped when moving from `from` to `to`, wrapping at `size`.
+fn wrapped_distance(from: usize, to: usize, size: usize) -> usize {
+    if to >= from {
+        to - from
+    } else {
+        size - from + to
+    }
+}
+
+fn overlaps(region: &FencedRegion, start: usize, end: usize, size: usize) -> bool {
+    let region_end = region.start + region.len;
+    let overlaps_linear = |a: usize, b: usize| start < b && a < end;
+    if region_end <= size {
+        overlaps_linear(region.start, region_end)
+    } else {
+        overlaps_linear(region.start, size) || overlaps_linear(0, region_end - size)
+    }
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let t = x % y;
+        x = y;
+        y = t;
+    }
+    a / x * b
+}
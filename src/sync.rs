@@ -0,0 +1,119 @@
+
+use std::ops::{BitOr, BitOrAssign};
+use gl::types::{GLsync, GLbitfield};
+use ReglResult;
+use ReglError;
+
+/// A fence sync object. It becomes signaled when the GL has finished all the commands issued
+/// before the fence was created.
+#[derive(Debug)]
+pub struct Fence {
+    sync: GLsync,
+}
+
+impl Fence {
+    pub fn new() -> Fence {
+        Fence { sync: glcall!(FenceSync(SYNC_GPU_COMMANDS_COMPLETE, 0)) }
+    }
+
+    /// Checks whether the fence has been signaled, without waiting.
+    pub fn is_signaled(&self) -> ReglResult<bool> {
+        self.client_wait(0, 0)
+    }
+
+    /// Waits for the fence to become signaled, at most `timeout_ns` nanoseconds. Returns false
+    /// on timeout, and fails with `FenceWaitFailed` if the wait itself failed, e.g. because the
+    /// context was lost.
+    pub fn wait(&self, timeout_ns: u64) -> ReglResult<bool> {
+        self.client_wait(::gl::SYNC_FLUSH_COMMANDS_BIT, timeout_ns)
+    }
+
+    fn client_wait(&self, flags: u32, timeout_ns: u64) -> ReglResult<bool> {
+        match glcall!(ClientWaitSync(self.sync, flags, timeout_ns)) {
+            ::gl::ALREADY_SIGNALED | ::gl::CONDITION_SATISFIED => Ok(true),
+            ::gl::TIMEOUT_EXPIRED => Ok(false),
+            _ => Err(ReglError::FenceWaitFailed),
+        }
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        glcall!(DeleteSync(self.sync));
+    }
+}
+
+/// Kinds of memory access ordered by `Context::memory_barrier` against the writes made by
+/// shaders before the barrier, e.g. by compute shaders into shader storage buffers.
+/// Combine kinds with `|`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct MemoryBarrier {
+    bits: GLbitfield,
+}
+
+impl MemoryBarrier {
+    /// Vertex attributes sourced from buffers.
+    pub const VERTEX_ATTRIB_ARRAY: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT };
+    /// Indices sourced from index buffers.
+    pub const ELEMENT_ARRAY: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::ELEMENT_ARRAY_BARRIER_BIT };
+    /// Uniform blocks sourced from uniform buffers.
+    pub const UNIFORM: MemoryBarrier = MemoryBarrier { bits: ::gl::UNIFORM_BARRIER_BIT };
+    pub const TEXTURE_FETCH: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::TEXTURE_FETCH_BARRIER_BIT };
+    pub const SHADER_IMAGE_ACCESS: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::SHADER_IMAGE_ACCESS_BARRIER_BIT };
+    /// Indirect draw and dispatch commands sourced from buffers.
+    pub const COMMAND: MemoryBarrier = MemoryBarrier { bits: ::gl::COMMAND_BARRIER_BIT };
+    pub const PIXEL_BUFFER: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::PIXEL_BUFFER_BARRIER_BIT };
+    pub const TEXTURE_UPDATE: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::TEXTURE_UPDATE_BARRIER_BIT };
+    /// Buffer reads, writes, copies and mappings other than persistent ones.
+    pub const BUFFER_UPDATE: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::BUFFER_UPDATE_BARRIER_BIT };
+    pub const FRAMEBUFFER: MemoryBarrier = MemoryBarrier { bits: ::gl::FRAMEBUFFER_BARRIER_BIT };
+    pub const TRANSFORM_FEEDBACK: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::TRANSFORM_FEEDBACK_BARRIER_BIT };
+    pub const ATOMIC_COUNTER: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::ATOMIC_COUNTER_BARRIER_BIT };
+    pub const SHADER_STORAGE: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::SHADER_STORAGE_BARRIER_BIT };
+    /// Client access to persistently mapped buffers, such as a `StreamBuffer`.
+    pub const CLIENT_MAPPED_BUFFER: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT };
+    pub const QUERY_BUFFER: MemoryBarrier =
+        MemoryBarrier { bits: ::gl::QUERY_BUFFER_BARRIER_BIT };
+    pub const ALL: MemoryBarrier = MemoryBarrier { bits: ::gl::ALL_BARRIER_BITS };
+
+    pub fn empty() -> MemoryBarrier {
+        MemoryBarrier { bits: 0 }
+    }
+
+    pub fn bits(&self) -> GLbitfield {
+        self.bits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn contains(&self, other: MemoryBarrier) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl BitOr for MemoryBarrier {
+    type Output = MemoryBarrier;
+
+    fn bitor(self, other: MemoryBarrier) -> MemoryBarrier {
+        MemoryBarrier { bits: self.bits | other.bits }
+    }
+}
+
+impl BitOrAssign for MemoryBarrier {
+    fn bitor_assign(&mut self, other: MemoryBarrier) {
+        self.bits |= other.bits;
+    }
+}
@@ -1,5 +1,5 @@
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use id::{Id, WeakId};
 
 #[derive(Debug)]
@@ -22,6 +22,37 @@ impl SimpleTracker {
     pub fn bind_none(&self) {
         self.current.set(WeakId::empty());
     }
+
+    pub fn unbind_if(&self, unbind: &Fn()) {
+        if self.current.get() != WeakId::empty() {
+            unbind();
+            self.current.set(WeakId::empty());
+        }
+    }
+}
+
+/// Tracks values of indexed state that is set by value rather than by binding an object.
+#[derive(Debug)]
+pub struct IndexedValueTracker<T> {
+    current: RefCell<Vec<Option<T>>>,
+}
+
+impl<T: Copy + PartialEq> IndexedValueTracker<T> {
+    pub fn new() -> IndexedValueTracker<T> {
+        IndexedValueTracker { current: RefCell::new(vec![]) }
+    }
+
+    pub fn set_if(&self, index: u32, value: &T, set: &Fn()) {
+        let index = index as usize;
+        let mut current = self.current.borrow_mut();
+        if current.len() <= index {
+            current.resize(index + 1, None);
+        }
+        if current[index].as_ref() != Some(value) {
+            set();
+            current[index] = Some(*value);
+        }
+    }
 }
 
 /// The type parameter on the trait is not actively used, but works as a discriminator,
@@ -33,3 +64,27 @@ pub trait BindIf<T> {
 pub trait BindNone<T> {
     fn bind_none(&self);
 }
+
+/// Unbinds whatever is bound, unless nothing is. Unlike `BindNone`, which only updates the
+/// tracker after the GL state has been changed otherwise.
+pub trait UnbindIf<T> {
+    fn unbind_if(&self, unbind: &Fn());
+}
+
+/// Counterpart of BindIf for state tracked with an IndexedValueTracker.
+pub trait SetIf<T> {
+    fn set_if(&self, index: u32, value: &T, set: &Fn());
+}
+
+/// What is bound to an indexed binding point: a range of a buffer.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct IndexedBinding {
+    pub id: WeakId,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// BindIf for indexed binding points, like the uniform buffer binding points.
+pub trait BindIndexedIf<T> {
+    fn bind_indexed_if(&self, index: u32, binding: &IndexedBinding, bind: &Fn());
+}
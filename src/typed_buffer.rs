@@ -0,0 +1,190 @@
+
+use std::marker::PhantomData;
+use std::mem::size_of;
+use ReglResult;
+use pod::Pod;
+use mapping::{MapAccess, ReadMapping, WriteMapping, ReadWriteMapping};
+use context::IndexType;
+use buffer::{Buffer, BufferCreationSupport, BufferTarget, BufferUsage};
+
+/// A buffer that remembers the type of its elements.
+///
+/// Offsets and lengths are counted in elements instead of bytes. Use `buffer()` to get the
+/// untyped buffer, e.g. for `VertexAttribute::vertex_buffer`.
+#[derive(Debug)]
+pub struct TypedBuffer<T: Pod> {
+    buffer: Buffer,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Pod> TypedBuffer<T> {
+    pub fn new<C: BufferCreationSupport>(support: &mut C,
+                                         target: BufferTarget,
+                                         usage: BufferUsage,
+                                         data: &[T])
+                                         -> ReglResult<TypedBuffer<T>> {
+        let buffer = try!(Buffer::new(support, target, usage, data));
+        Ok(TypedBuffer {
+            buffer: buffer,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Creates a buffer with room for `len` elements, without initializing them.
+    pub fn with_len<C: BufferCreationSupport>(support: &mut C,
+                                              target: BufferTarget,
+                                              usage: BufferUsage,
+                                              len: usize)
+                                              -> ReglResult<TypedBuffer<T>> {
+        let buffer = try!(Buffer::with_size(support, target, usage, len * size_of::<T>()));
+        Ok(TypedBuffer {
+            buffer: buffer,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        element_count::<T>(self.buffer.data_len())
+    }
+
+    /// See `Buffer::resize`.
+    pub fn resize(&self, len: usize, preserve_contents: bool) -> ReglResult<()> {
+        self.buffer.resize(len * size_of::<T>(), preserve_contents)
+    }
+
+    /// Maps `len` elements starting from the element at `first_element` for reading. See
+    /// `Buffer::map_range_read`.
+    pub fn map_range_read(&mut self,
+                          first_element: usize,
+                          len: usize,
+                          access: MapAccess)
+                          -> ReglResult<ReadMapping<'_, T>> {
+        self.buffer.map_range_read(first_element * size_of::<T>(), len, access)
+    }
+
+    /// See `Buffer::map_range_write`.
+    pub fn map_range_write(&mut self,
+                           first_element: usize,
+                           len: usize,
+                           access: MapAccess)
+                           -> ReglResult<WriteMapping<'_, T>> {
+        self.buffer.map_range_write(first_element * size_of::<T>(), len, access)
+    }
+
+    /// See `Buffer::map_range_read_write`.
+    pub fn map_range_read_write(&mut self,
+                                first_element: usize,
+                                len: usize,
+                                access: MapAccess)
+                                -> ReglResult<ReadWriteMapping<'_, T>> {
+        self.buffer.map_range_read_write(first_element * size_of::<T>(), len, access)
+    }
+
+    /// See `Buffer::orphan`.
+    pub fn orphan(&self) -> ReglResult<()> {
+        self.buffer.orphan()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Overwrites elements starting from the element at `first_element`.
+    pub fn update(&self, first_element: usize, data: &[T]) -> ReglResult<()> {
+        self.buffer.update_data(first_element * size_of::<T>(), data)
+    }
+
+    /// Reads `data.len()` elements starting from the element at `first_element`.
+    pub fn read(&self, first_element: usize, data: &mut [T]) -> ReglResult<()> {
+        self.buffer.read_data(first_element * size_of::<T>(), data)
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+/// Types that can be used as vertex indices.
+pub trait IndexElement : Pod {
+    fn index_type() -> IndexType;
+}
+
+impl IndexElement for u8 {
+    fn index_type() -> IndexType {
+        IndexType::UByte
+    }
+}
+
+impl IndexElement for u16 {
+    fn index_type() -> IndexType {
+        IndexType::UShort
+    }
+}
+
+impl IndexElement for u32 {
+    fn index_type() -> IndexType {
+        IndexType::UInt
+    }
+}
+
+/// An index buffer of `u8`, `u16` or `u32` indices. A vertex array created with one knows its
+/// index type, so that `Context::draw_elements` doesn't need to be told.
+#[derive(Debug)]
+pub struct IndexBuffer<I: IndexElement> {
+    buffer: TypedBuffer<I>,
+}
+
+impl<I: IndexElement> IndexBuffer<I> {
+    pub fn new<C: BufferCreationSupport>(support: &mut C,
+                                         usage: BufferUsage,
+                                         indices: &[I])
+                                         -> ReglResult<IndexBuffer<I>> {
+        let buffer = try!(TypedBuffer::new(support, BufferTarget::IndexBuffer, usage, indices));
+        Ok(IndexBuffer { buffer: buffer })
+    }
+
+    /// Creates an index buffer with room for `len` indices, without initializing them.
+    pub fn with_len<C: BufferCreationSupport>(support: &mut C,
+                                              usage: BufferUsage,
+                                              len: usize)
+                                              -> ReglResult<IndexBuffer<I>> {
+        let buffer = try!(TypedBuffer::with_len(support, BufferTarget::IndexBuffer, usage, len));
+        Ok(IndexBuffer { buffer: buffer })
+    }
+
+    /// Number of indices in the buffer.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// See `Buffer::resize`.
+    pub fn resize(&self, len: usize, preserve_contents: bool) -> ReglResult<()> {
+        self.buffer.resize(len, preserve_contents)
+    }
+
+    /// See `Buffer::orphan`.
+    pub fn orphan(&self) -> ReglResult<()> {
+        self.buffer.orphan()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Overwrites indices starting from the index at `first_index`.
+    pub fn update(&self, first_index: usize, indices: &[I]) -> ReglResult<()> {
+        self.buffer.update(first_index, indices)
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        self.buffer.buffer()
+    }
+}
+
+fn element_count<T>(byte_len: usize) -> usize {
+    match size_of::<T>() {
+        0 => 0,
+        element_size => byte_len / element_size,
+    }
+}
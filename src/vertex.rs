@@ -0,0 +1,236 @@
+
+use std::mem::size_of;
+use ReglResult;
+use ReglError;
+use buffer::Buffer;
+use program::Program;
+use vertex_array::{VertexAttribute, VertexAttributeType};
+
+/// How a field of a vertex struct is bound to a shader attribute.
+#[derive(Debug,Clone,Copy)]
+pub enum VertexLocation {
+    /// Explicit attribute index.
+    Index(u32),
+    /// Name of the shader attribute, resolved against a program.
+    Name(&'static str),
+}
+
+/// Layout of a single field of a vertex struct. Usually generated by `#[derive(Vertex)]`.
+#[derive(Debug,Clone,Copy)]
+pub struct VertexField {
+    pub location: VertexLocation,
+    pub size: u8,
+    pub attribute_type: VertexAttributeType,
+    pub normalized: bool,
+    pub integer: bool,
+    /// Offset of the field from the beginning of the struct, in bytes.
+    pub offset: u32,
+}
+
+/// A type that can be a field of a vertex struct.
+pub trait VertexComponent {
+    /// Number of components, 1 to 4.
+    fn size() -> u8;
+    fn attribute_type() -> VertexAttributeType;
+}
+
+/// A vertex component that can be read as an integer attribute (glVertexAttribIPointer), as
+/// required by `#[vertex(integer)]`. Floating point components can't.
+#[diagnostic::on_unimplemented(message = "`{Self}` can't be read as an integer vertex attribute",
+                               label = "`#[vertex(integer)]` requires integer components")]
+pub trait IntegerVertexComponent : VertexComponent {}
+
+macro_rules! vertex_component {
+    ($t:ty, $attribute_type:ident) => (
+        impl VertexComponent for $t {
+            fn size() -> u8 { 1 }
+            fn attribute_type() -> VertexAttributeType { VertexAttributeType::$attribute_type }
+        }
+        vertex_component!([$t; 1], 1, $attribute_type);
+        vertex_component!([$t; 2], 2, $attribute_type);
+        vertex_component!([$t; 3], 3, $attribute_type);
+        vertex_component!([$t; 4], 4, $attribute_type);
+    );
+    ($t:ty, $size:expr, $attribute_type:ident) => (
+        impl VertexComponent for $t {
+            fn size() -> u8 { $size }
+            fn attribute_type() -> VertexAttributeType { VertexAttributeType::$attribute_type }
+        }
+    );
+}
+
+vertex_component!(i8, Byte);
+vertex_component!(u8, UnsignedByte);
+vertex_component!(i16, Short);
+vertex_component!(u16, UnsignedShort);
+vertex_component!(i32, Int);
+vertex_component!(u32, UnsignedInt);
+vertex_component!(f32, Float);
+vertex_component!(f64, Double);
+
+macro_rules! integer_vertex_component {
+    ($($t:ty),*) => (
+        $(
+            impl IntegerVertexComponent for $t {}
+            impl IntegerVertexComponent for [$t; 1] {}
+            impl IntegerVertexComponent for [$t; 2] {}
+            impl IntegerVertexComponent for [$t; 3] {}
+            impl IntegerVertexComponent for [$t; 4] {}
+        )*
+    );
+}
+
+integer_vertex_component!(i8, u8, i16, u16, i32, u32);
+
+/// A `#[repr(C)]` struct describing one vertex in a vertex buffer. Use `#[derive(Vertex)]`
+/// to implement this.
+pub trait Vertex : Sized {
+    /// The fields of the vertex, in declaration order.
+    fn fields() -> Vec<VertexField>;
+
+    /// Distance between consecutive vertices in a buffer, in bytes.
+    fn stride() -> u32 {
+        size_of::<Self>() as u32
+    }
+
+    /// Vertex attributes for reading vertices of this type from `buffer`, starting at byte
+    /// `offset`. Every field must have an explicit location; use `attributes_for_program`
+    /// when fields are bound by name.
+    fn attributes<'a>(buffer: &'a Buffer, offset: u32) -> ReglResult<Vec<VertexAttribute<'a>>> {
+        Self::fields()
+            .iter()
+            .map(|field| {
+                match field.location {
+                    VertexLocation::Index(index) =>
+                        Ok(field_attribute::<Self>(field, index, buffer, offset)),
+                    VertexLocation::Name(name) =>
+                        Err(ReglError::UnresolvedAttributeName(name.to_string())),
+                }
+            })
+            .collect()
+    }
+
+    /// Like `attributes`, but fields bound by name are resolved against the active attributes
+    /// of `program`.
+    fn attributes_for_program<'a>(program: &Program,
+                                  buffer: &'a Buffer,
+                                  offset: u32)
+                                  -> ReglResult<Vec<VertexAttribute<'a>>> {
+        Self::fields()
+            .iter()
+            .map(|field| {
+                let index = match field.location {
+                    VertexLocation::Index(index) => index,
+                    VertexLocation::Name(name) => {
+                        let location = try!(program.attribute_location(name));
+                        if location < 0 {
+                            return Err(ReglError::InactiveAttribute(name.to_string()));
+                        }
+                        location as u32
+                    }
+                };
+                Ok(field_attribute::<Self>(field, index, buffer, offset))
+            })
+            .collect()
+    }
+}
+
+fn field_attribute<'a, V: Vertex>(field: &VertexField,
+                                  index: u32,
+                                  buffer: &'a Buffer,
+                                  offset: u32)
+                                  -> VertexAttribute<'a> {
+    VertexAttribute {
+        index: index,
+        size: field.size,
+        attribute_type: field.attribute_type,
+        normalized: field.normalized,
+        integer: field.integer,
+        stride: V::stride(),
+        offset: offset + field.offset,
+        vertex_buffer: buffer,
+    }
+}
+
+/// Checks that `#[derive(Vertex)]` rejects invalid field attributes at compile time. The first
+/// example compiles, so that the others can only fail because of the attribute.
+///
+/// ```
+/// extern crate regl;
+/// use regl::Vertex;
+///
+/// #[derive(Vertex)]
+/// #[repr(C)]
+/// struct MyVertex {
+///     #[vertex(integer)]
+///     material: [u32; 2],
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// extern crate regl;
+/// use regl::Vertex;
+///
+/// #[derive(Vertex)]
+/// #[repr(C)]
+/// struct MyVertex {
+///     #[vertex(integer)]
+///     position: [f32; 3],
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// extern crate regl;
+/// use regl::Vertex;
+///
+/// #[derive(Vertex)]
+/// #[repr(C)]
+/// struct MyVertex {
+///     #[vertex(integer)]
+///     weight: f64,
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// extern crate regl;
+/// use regl::Vertex;
+///
+/// #[derive(Vertex)]
+/// #[repr(C)]
+/// struct MyVertex {
+///     #[vertex(normalized, integer)]
+///     color: [u8; 4],
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// extern crate regl;
+/// use regl::Vertex;
+///
+/// #[derive(Vertex)]
+/// #[repr(C)]
+/// struct MyVertex {
+///     #[vertex(location = 0, name = "in_position")]
+///     position: [f32; 3],
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// extern crate regl;
+/// use regl::Vertex;
+///
+/// #[derive(Vertex)]
+/// #[repr(C)]
+/// struct MyVertex {
+///     #[vertex(offset = 4)]
+///     position: [f32; 3],
+/// }
+/// # fn main() {}
+/// ```
+#[cfg(doctest)]
+pub struct DeriveVertexCompileErrors;
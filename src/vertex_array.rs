@@ -5,12 +5,16 @@ use std::fmt::Debug;
 use gl::types::{GLenum, GLuint, GLint, GLboolean, GLsizei, GLvoid};
 use id::{Id, GenerateId, GlId};
 use ReglResult;
-use tracker::{BindIf, BindNone};
+use ReglError;
+use tracker::{BindIf, BindNone, SetIf};
 use resource::ResourceCreationSupport;
 use buffer::{Buffer, BaseBuffer, BufferTarget, IndexBufferTag, get_base_buffer};
+use program::{Program, ShaderAttribute, ShaderAttributeType};
+use typed_buffer::{IndexBuffer, IndexElement};
+use context::IndexType;
 
 pub trait VertexArraySupport : BindIf<VertexArray> + BindIf<IndexBufferTag>
-    + BindNone<IndexBufferTag> + Debug {
+    + BindNone<IndexBufferTag> + SetIf<ConstantAttribute> + Debug {
     fn separate_ibo_binding(&self) -> bool;
 }
 
@@ -25,6 +29,39 @@ pub struct VertexArray {
     gl_id: GlId,
     attributes: Vec<StoredVertexAttribute>,
     index_buffer: Option<Rc<BaseBuffer>>,
+    index_type: Option<IndexType>,
+    constant_attributes: Vec<(u32, ConstantAttribute)>,
+}
+
+/// Value of a generic vertex attribute, read by the shader when the attribute has no enabled
+/// array. Unlike the arrays, these values are context state; they are uploaded only when
+/// they differ from the value currently set.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum ConstantAttribute {
+    /// glVertexAttrib4f
+    Float([f32; 4]),
+    /// glVertexAttribI4i
+    Int([i32; 4]),
+    /// glVertexAttribI4ui
+    UnsignedInt([u32; 4]),
+}
+
+impl From<[f32; 4]> for ConstantAttribute {
+    fn from(value: [f32; 4]) -> ConstantAttribute {
+        ConstantAttribute::Float(value)
+    }
+}
+
+impl From<[i32; 4]> for ConstantAttribute {
+    fn from(value: [i32; 4]) -> ConstantAttribute {
+        ConstantAttribute::Int(value)
+    }
+}
+
+impl From<[u32; 4]> for ConstantAttribute {
+    fn from(value: [u32; 4]) -> ConstantAttribute {
+        ConstantAttribute::UnsignedInt(value)
+    }
 }
 
 #[derive(Copy,Clone,Debug)]
@@ -66,6 +103,24 @@ pub struct VertexAttribute<'a> {
     pub size: u8,
     pub attribute_type: VertexAttributeType,
     pub normalized: bool,
+    /// Integer attributes are passed to the shader as integers (glVertexAttribIPointer)
+    /// instead of being converted to floats.
+    pub integer: bool,
+    pub stride: u32,
+    pub offset: u32,
+    pub vertex_buffer: &'a Buffer,
+}
+
+/// Vertex attribute that is bound to a program's attribute by name instead of index.
+/// See `VertexArray::for_program`.
+#[derive(Copy,Clone,Debug)]
+pub struct NamedVertexAttribute<'a> {
+    pub name: &'a str,
+    /// Number of components. For matrix attributes, the number of rows.
+    pub size: u8,
+    pub attribute_type: VertexAttributeType,
+    pub normalized: bool,
+    pub integer: bool,
     pub stride: u32,
     pub offset: u32,
     pub vertex_buffer: &'a Buffer,
@@ -77,9 +132,11 @@ struct StoredVertexAttribute {
     pub size: u8,
     pub attribute_type: VertexAttributeType,
     pub normalized: bool,
+    pub integer: bool,
     pub stride: u32,
     pub offset: u32,
     pub vertex_buffer: Rc<BaseBuffer>,
+    pub enabled: bool,
 }
 
 impl VertexArray {
@@ -99,6 +156,8 @@ impl VertexArray {
             gl_id: gl_id,
             attributes: attributes.into_iter().map(|a| into_stored(a.borrow())).collect(),
             index_buffer: index_buffer.map(|b| get_base_buffer(b).clone()),
+            index_type: None,
+            constant_attributes: vec![],
         };
         vertex_array.bind();
         setup_vertex_array(&vertex_array.attributes[..],
@@ -106,6 +165,143 @@ impl VertexArray {
         Ok(vertex_array)
     }
 
+    /// Like `new`, but the vertex array also remembers the type of the indices.
+    pub fn new_indexed<'a, C, I, A, E>(support: &mut C,
+                                       attributes: I,
+                                       index_buffer: &IndexBuffer<E>)
+                                       -> ReglResult<VertexArray>
+        where C: ResourceCreationSupport,
+              I: IntoIterator<Item = A>,
+              A: Borrow<VertexAttribute<'a>>,
+              E: IndexElement
+    {
+        let mut vertex_array = try!(VertexArray::new(support,
+                                                     attributes,
+                                                     Some(index_buffer.buffer())));
+        vertex_array.index_type = Some(E::index_type());
+        Ok(vertex_array)
+    }
+
+    /// Creates a vertex array whose attributes are looked up by name from the active attributes
+    /// of `program`. Fails if an attribute is not active in the program, or if the given format
+    /// can't feed the type the shader declares.
+    ///
+    /// Matrix attributes take up one location per column; they are set up automatically,
+    /// with the columns following each other tightly in the buffer.
+    pub fn for_program<'a, C, I, A>(support: &mut C,
+                                    program: &Program,
+                                    attributes: I,
+                                    index_buffer: Option<&Buffer>)
+                                    -> ReglResult<VertexArray>
+        where C: ResourceCreationSupport,
+              I: IntoIterator<Item = A>,
+              A: Borrow<NamedVertexAttribute<'a>>
+    {
+        let attribute_info = program.attribute_info();
+        let mut resolved = vec![];
+        for attribute in attributes {
+            let attribute = attribute.borrow();
+            let shader_attribute = match attribute_info.find(attribute.name) {
+                Some(shader_attribute) => shader_attribute,
+                None => return Err(ReglError::InactiveAttribute(attribute.name.to_string())),
+            };
+            try!(resolve_named(attribute, shader_attribute, &mut resolved));
+        }
+        VertexArray::new(support, resolved, index_buffer)
+    }
+
+    /// Replaces the index buffer of the vertex array, or removes it if `None` is given.
+    pub fn set_index_buffer(&mut self, index_buffer: Option<&Buffer>) {
+        self.bind();
+        match index_buffer {
+            Some(buffer) => get_base_buffer(buffer).bind_as_indices_anyway(),
+            None => {
+                glcall!(BindBuffer(ELEMENT_ARRAY_BUFFER, 0));
+                BindNone::<IndexBufferTag>::bind_none(&*self.shared_context);
+            }
+        }
+        self.index_buffer = index_buffer.map(|b| get_base_buffer(b).clone());
+        self.index_type = None;
+    }
+
+    /// Like `set_index_buffer`, but the vertex array also remembers the type of the indices.
+    pub fn set_typed_index_buffer<E: IndexElement>(&mut self, index_buffer: &IndexBuffer<E>) {
+        self.set_index_buffer(Some(index_buffer.buffer()));
+        self.index_type = Some(E::index_type());
+    }
+
+    /// Type of the indices, if the index buffer was given as an `IndexBuffer`.
+    pub fn index_type(&self) -> Option<IndexType> {
+        self.index_type
+    }
+
+    /// Number of indices in the index buffer, if the index buffer was given as an
+    /// `IndexBuffer`.
+    pub fn index_count(&self) -> Option<usize> {
+        match (&self.index_buffer, self.index_type) {
+            (&Some(ref ibo), Some(index_type)) =>
+                Some(ibo.data_len() / index_type.byte_size() as usize),
+            _ => None,
+        }
+    }
+
+    /// Sets up the attribute at `attribute.index`, replacing the old attribute with the same
+    /// index if there was one. The attribute is enabled.
+    pub fn set_attribute(&mut self, attribute: &VertexAttribute) {
+        let stored = into_stored(attribute);
+        self.bind();
+        setup_vertex_attribute(&stored);
+        match self.attributes.iter().position(|a| a.index == stored.index) {
+            Some(position) => self.attributes[position] = stored,
+            None => self.attributes.push(stored),
+        }
+    }
+
+    /// Enables a previously set up attribute.
+    pub fn enable_attribute(&mut self, index: u32) -> ReglResult<()> {
+        self.set_attribute_enabled(index, true)
+    }
+
+    /// Disables an attribute without forgetting its buffer and layout, so that it can be
+    /// enabled again later.
+    pub fn disable_attribute(&mut self, index: u32) -> ReglResult<()> {
+        self.set_attribute_enabled(index, false)
+    }
+
+    fn set_attribute_enabled(&mut self, index: u32, enabled: bool) -> ReglResult<()> {
+        let position = match self.attributes.iter().position(|a| a.index == index) {
+            Some(position) => position,
+            None => return Err(ReglError::UnknownVertexAttribute),
+        };
+        if self.attributes[position].enabled != enabled {
+            self.bind();
+            if enabled {
+                glcall!(EnableVertexAttribArray(index));
+            } else {
+                glcall!(DisableVertexAttribArray(index));
+            }
+            self.attributes[position].enabled = enabled;
+        }
+        Ok(())
+    }
+
+    /// Sets the value the shader reads from the attribute at `index` whenever the vertex array
+    /// doesn't have an enabled array for it. The value is applied when the vertex array is
+    /// bound for drawing.
+    pub fn set_constant_attribute<T: Into<ConstantAttribute>>(&mut self, index: u32, value: T) {
+        let value = value.into();
+        match self.constant_attributes.iter().position(|&(i, _)| i == index) {
+            Some(position) => self.constant_attributes[position].1 = value,
+            None => self.constant_attributes.push((index, value)),
+        }
+    }
+
+    /// Forgets the constant value of the attribute at `index`. The shader will read whatever
+    /// value was set last.
+    pub fn clear_constant_attribute(&mut self, index: u32) {
+        self.constant_attributes.retain(|&(i, _)| i != index);
+    }
+
     fn gl_bind(&self) {
         glcall!(BindVertexArray(self.gl_id));
     }
@@ -125,9 +321,25 @@ impl VertexArrayInternal for VertexArray {
                 BindIf::<IndexBufferTag>::bind_if(shared_context, &ibo.get_id(), &|| ()),
             (&None, _) => BindNone::<IndexBufferTag>::bind_none(shared_context),
         }
+        for &(index, ref value) in &self.constant_attributes {
+            set_constant_attribute(shared_context, index, value);
+        }
     }
 }
 
+pub fn set_constant_attribute(shared_context: &VertexArraySupport,
+                              index: u32,
+                              value: &ConstantAttribute) {
+    shared_context.set_if(index, value, &|| {
+        match *value {
+            ConstantAttribute::Float(v) => glcall!(VertexAttrib4f(index, v[0], v[1], v[2], v[3])),
+            ConstantAttribute::Int(v) => glcall!(VertexAttribI4i(index, v[0], v[1], v[2], v[3])),
+            ConstantAttribute::UnsignedInt(v) =>
+                glcall!(VertexAttribI4ui(index, v[0], v[1], v[2], v[3])),
+        }
+    });
+}
+
 /// Expects that the vertex array has already been bound
 fn setup_vertex_array(attributes: &[StoredVertexAttribute],
                       index_buffer: Option<&Rc<BaseBuffer>>) {
@@ -135,8 +347,21 @@ fn setup_vertex_array(attributes: &[StoredVertexAttribute],
         ibo.bind_as_indices_anyway();
     }
     for attribute in attributes {
-        attribute.vertex_buffer.bind_target(BufferTarget::VertexBuffer);
-        glcall!(EnableVertexAttribArray(attribute.index));
+        setup_vertex_attribute(attribute);
+    }
+}
+
+/// Expects that the vertex array has already been bound
+fn setup_vertex_attribute(attribute: &StoredVertexAttribute) {
+    attribute.vertex_buffer.bind_target(BufferTarget::VertexBuffer);
+    glcall!(EnableVertexAttribArray(attribute.index));
+    if attribute.integer {
+        glcall!(VertexAttribIPointer(attribute.index as GLuint,
+                                     attribute.size as GLint,
+                                     attribute_to_gl_type(attribute.attribute_type),
+                                     attribute.stride as GLsizei,
+                                     attribute.offset as *const GLvoid));
+    } else {
         glcall!(VertexAttribPointer(attribute.index as GLuint,
                                     attribute.size as GLint,
                                     attribute_to_gl_type(attribute.attribute_type),
@@ -167,10 +392,109 @@ fn into_stored<'a>(attribute: &VertexAttribute<'a>) -> StoredVertexAttribute {
         size: attribute.size,
         attribute_type: attribute.attribute_type,
         normalized: attribute.normalized,
+        integer: attribute.integer,
         stride: attribute.stride,
         offset: attribute.offset,
         vertex_buffer: get_base_buffer(attribute.vertex_buffer).clone(),
+        enabled: true,
+    }
+}
+
+#[derive(PartialEq)]
+enum ComponentKind {
+    Float,
+    Int,
+    UnsignedInt,
+}
+
+/// Returns the kind of the components, the number of rows and the number of columns.
+fn shader_attribute_shape(attribute_type: ShaderAttributeType)
+                          -> Option<(ComponentKind, u8, u32)> {
+    use self::ComponentKind::*;
+    match attribute_type {
+        ShaderAttributeType::Float => Some((Float, 1, 1)),
+        ShaderAttributeType::FloatVec2 => Some((Float, 2, 1)),
+        ShaderAttributeType::FloatVec3 => Some((Float, 3, 1)),
+        ShaderAttributeType::FloatVec4 => Some((Float, 4, 1)),
+        ShaderAttributeType::FloatMat2 => Some((Float, 2, 2)),
+        ShaderAttributeType::FloatMat3 => Some((Float, 3, 3)),
+        ShaderAttributeType::FloatMat4 => Some((Float, 4, 4)),
+        ShaderAttributeType::FloatMat2x3 => Some((Float, 3, 2)),
+        ShaderAttributeType::FloatMat2x4 => Some((Float, 4, 2)),
+        ShaderAttributeType::FloatMat3x2 => Some((Float, 2, 3)),
+        ShaderAttributeType::FloatMat3x4 => Some((Float, 4, 3)),
+        ShaderAttributeType::FloatMat4x2 => Some((Float, 2, 4)),
+        ShaderAttributeType::FloatMat4x3 => Some((Float, 3, 4)),
+        ShaderAttributeType::Int => Some((Int, 1, 1)),
+        ShaderAttributeType::IntVec2 => Some((Int, 2, 1)),
+        ShaderAttributeType::IntVec3 => Some((Int, 3, 1)),
+        ShaderAttributeType::IntVec4 => Some((Int, 4, 1)),
+        ShaderAttributeType::UnsignedInt => Some((UnsignedInt, 1, 1)),
+        ShaderAttributeType::UnsignedIntVec2 => Some((UnsignedInt, 2, 1)),
+        ShaderAttributeType::UnsignedIntVec3 => Some((UnsignedInt, 3, 1)),
+        ShaderAttributeType::UnsignedIntVec4 => Some((UnsignedInt, 4, 1)),
+        ShaderAttributeType::UnrecognizedType(_) => None,
+    }
+}
+
+fn integer_kind(attribute_type: VertexAttributeType) -> Option<ComponentKind> {
+    match attribute_type {
+        VertexAttributeType::Byte |
+        VertexAttributeType::Short |
+        VertexAttributeType::Int => Some(ComponentKind::Int),
+        VertexAttributeType::UnsignedByte |
+        VertexAttributeType::UnsignedShort |
+        VertexAttributeType::UnsignedInt => Some(ComponentKind::UnsignedInt),
+        _ => None,
+    }
+}
+
+fn resolve_named<'a>(attribute: &NamedVertexAttribute<'a>,
+                     shader_attribute: &ShaderAttribute,
+                     resolved: &mut Vec<VertexAttribute<'a>>)
+                     -> ReglResult<()> {
+    let mismatch = |reason: &str| {
+        Err(ReglError::AttributeTypeMismatch(format!("attribute {}: shader declares {:?}, {}",
+                                                     attribute.name,
+                                                     shader_attribute.attribute_type,
+                                                     reason)))
+    };
+    let (kind, rows, columns) = match shader_attribute_shape(shader_attribute.attribute_type) {
+        Some(shape) => shape,
+        None => return mismatch("which is not supported"),
+    };
+    match kind {
+        ComponentKind::Float if attribute.integer =>
+            return mismatch("but the attribute is given in integer mode"),
+        ComponentKind::Float => (),
+        _ if !attribute.integer =>
+            return mismatch("which requires the attribute to be given in integer mode"),
+        _ if integer_kind(attribute.attribute_type) != Some(kind) =>
+            return mismatch(&format!("which can't be read from {:?}", attribute.attribute_type)),
+        _ => (),
+    }
+    if attribute.size < 1 || attribute.size > 4 {
+        return mismatch(&format!("but the attribute size is {}", attribute.size));
+    }
+    if columns > 1 && attribute.size != rows {
+        return mismatch(&format!("which has {} rows, but the attribute size is {}",
+                                 rows,
+                                 attribute.size));
+    }
+    let column_size = attribute.size as u32 * attribute.attribute_type.byte_size();
+    for column in 0..columns {
+        resolved.push(VertexAttribute {
+            index: shader_attribute.location as u32 + column,
+            size: attribute.size,
+            attribute_type: attribute.attribute_type,
+            normalized: attribute.normalized,
+            integer: attribute.integer,
+            stride: attribute.stride,
+            offset: attribute.offset + column * column_size,
+            vertex_buffer: attribute.vertex_buffer,
+        });
     }
+    Ok(())
 }
 
 fn attribute_to_gl_type(attribute_type: VertexAttributeType) -> GLenum {
@@ -0,0 +1,50 @@
+extern crate regl;
+
+use regl::{Vertex, VertexLocation, VertexAttributeType};
+
+#[derive(Vertex)]
+#[repr(C)]
+#[allow(dead_code)]
+struct TestVertex {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+    #[vertex(name = "in_color", normalized)]
+    color: [u8; 4],
+    #[vertex(integer)]
+    material: u32,
+    weights: [u16; 2],
+}
+
+#[test]
+fn derives_offsets_and_sizes() {
+    let fields = TestVertex::fields();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields.iter().map(|f| f.offset).collect::<Vec<_>>(), vec![0, 12, 16, 20]);
+    assert_eq!(fields.iter().map(|f| f.size).collect::<Vec<_>>(), vec![3, 4, 1, 2]);
+    assert_eq!(TestVertex::stride(), 24);
+}
+
+#[test]
+fn derives_attribute_types() {
+    let fields = TestVertex::fields();
+    assert!(matches!(fields[0].attribute_type, VertexAttributeType::Float));
+    assert!(matches!(fields[1].attribute_type, VertexAttributeType::UnsignedByte));
+    assert!(matches!(fields[2].attribute_type, VertexAttributeType::UnsignedInt));
+    assert!(matches!(fields[3].attribute_type, VertexAttributeType::UnsignedShort));
+}
+
+#[test]
+fn derives_flags() {
+    let fields = TestVertex::fields();
+    let flags: Vec<_> = fields.iter().map(|f| (f.normalized, f.integer)).collect();
+    assert_eq!(flags, vec![(false, false), (true, false), (false, true), (false, false)]);
+}
+
+#[test]
+fn derives_locations() {
+    let fields = TestVertex::fields();
+    assert!(matches!(fields[0].location, VertexLocation::Index(0)));
+    assert!(matches!(fields[1].location, VertexLocation::Name("in_color")));
+    assert!(matches!(fields[2].location, VertexLocation::Name("material")));
+    assert!(matches!(fields[3].location, VertexLocation::Name("weights")));
+}